@@ -0,0 +1,853 @@
+use crate::doctor::{diagnose, Severity};
+use crate::error::{Error, Result};
+use crate::files::{
+    list_profiles, paths, read_lines, touch_file, unix_now, update_cache, update_lines,
+    update_projects, DEFAULT_PROFILE,
+};
+use crate::git::{self, clone, linked_worktrees, main_worktree, Remote};
+use crate::history;
+use crate::multiplexer::Multiplexer;
+use crate::output::{build_records, print_records, OutputFormat};
+use crate::picker::{
+    best_match, select_with_fzf, select_with_query, start_fzf, wait_for_fzf_selection,
+    write_entries, write_statuses, PickerChoice,
+};
+use crate::project::{
+    absolute_path, entry_projects, expand_entry, get_cached_projects, get_candidates, get_projects,
+    is_pattern, load_and_filter_projects, FilterExists, Project,
+};
+use crate::projects_file::{read_entries, Line, ProjectEntry};
+use crate::scaffold::Scaffold;
+use crate::servers::Servers;
+use crate::template::{open_session, Window};
+use crate::tmux::Tmux;
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+use std::{env, thread};
+use tempfile::TempDir;
+
+/// Adds `dir`, or the current directory, to the projects file with its
+/// symlinks resolved, unless `logical` keeps them.
+///
+/// # Errors
+/// Returns an error if the projects file cannot be updated.
+pub fn add_project(dir: Option<&str>, depth: Option<u32>, logical: bool) -> Result<()> {
+    let project = Project::new(&absolute_path(dir.unwrap_or("."), logical)?);
+    add_entry(&project, depth)?;
+    if let Some(depth_value) = depth {
+        println!(
+            "Added \"{}\" to .projects with depth {}",
+            project.shortened_path, depth_value
+        );
+    } else {
+        println!("Added \"{}\" to .projects", project.shortened_path);
+    }
+    Ok(())
+}
+
+/// Adds `project` to the projects file, or sets the depth of its entry.
+fn add_entry(project: &Project, depth: Option<u32>) -> Result<()> {
+    let projects_file = paths()?.projects_file.clone();
+    touch_file(&projects_file)?;
+    update_projects(&projects_file, |lines| {
+        // An existing entry for this directory, through any path, keeps its
+        // place and other options
+        if let Some((index, mut entry)) = find_entry(lines, &project.canonical_path) {
+            entry.depth = depth;
+            lines[index] = entry.to_line();
+        } else {
+            lines.push(
+                ProjectEntry {
+                    depth,
+                    ..ProjectEntry::new(&project.shortened_path)
+                }
+                .to_line(),
+            );
+        }
+    })
+}
+
+/// The project line of `lines` for the directory `key`, as given by
+/// [`ProjectEntry::key`], along with its index.
+fn find_entry(lines: &[String], key: &str) -> Option<(usize, ProjectEntry)> {
+    lines.iter().enumerate().find_map(|(index, line)| {
+        Line::project(line)
+            .filter(|entry| entry.key() == key)
+            .map(|entry| (index, entry))
+    })
+}
+
+/// The project lines of the projects file, without comments, blank lines
+/// and includes, for choosing one with fzf.
+fn project_lines(projects_file: &Path) -> Vec<String> {
+    let mut lines = read_lines(projects_file).unwrap_or_default();
+    lines.retain(|line| Line::project(line).is_some());
+    lines
+}
+
+/// The entry of the projects file for `dir`, written either as in the
+/// file or as any path to the same directory.
+///
+/// # Errors
+/// Returns an error if the projects file has no entry for `dir`.
+fn entry_for(lines: &[String], dir: &str) -> Result<ProjectEntry> {
+    let key = Project::new(&absolute_path(dir, true)?).canonical_path;
+    lines
+        .iter()
+        .filter_map(|line| Line::project(line))
+        .find(|entry| entry.dir == dir || entry.key() == key)
+        .ok_or_else(|| Error::UnknownProject(dir.to_string()))
+}
+
+/// Asks a yes/no question on the terminal.
+///
+/// # Errors
+/// Returns an error if there is no terminal to answer on, as in scripts,
+/// which have to pass `--yes` instead, or the answer cannot be read.
+fn confirm(question: &str) -> Result<bool> {
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        return Err(Error::Config(format!(
+            "\"{question}\" needs an answer from a terminal; pass --yes to skip it"
+        )));
+    }
+    print!("{question} [y/N] ");
+    std::io::stdout()
+        .flush()
+        .map_err(|e| Error::io("<stdout>", e))?;
+    let mut answer = String::new();
+    stdin
+        .read_line(&mut answer)
+        .map_err(|e| Error::io("<stdin>", e))?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Removes the entries for `dirs` from the projects file, after asking
+/// unless `yes` is set, or a project chosen with fzf if `dirs` is empty.
+///
+/// # Errors
+/// Returns an error if nothing is selected, an entry doesn't exist or the
+/// file cannot be updated. Nothing is removed unless every entry exists.
+pub fn delete_project(dirs: &[String], yes: bool) -> Result<()> {
+    let projects_file = paths()?.projects_file.clone();
+    let lines = read_lines(&projects_file).unwrap_or_default();
+    let selected: Vec<ProjectEntry> = if dirs.is_empty() {
+        let selected_str = select_with_fzf(&project_lines(&projects_file))?;
+        vec![Line::project(&selected_str).ok_or(Error::NoSelection)?]
+    } else {
+        let selected = dirs
+            .iter()
+            .map(|dir| entry_for(&lines, dir))
+            .collect::<Result<Vec<_>>>()?;
+        let names: Vec<&str> = selected.iter().map(|entry| entry.dir.as_str()).collect();
+        if !yes && !confirm(&format!("Delete {} from .projects?", names.join(", ")))? {
+            println!("Nothing deleted");
+            return Ok(());
+        }
+        selected
+    };
+    // The file may have changed while the picker was open, so the entries
+    // are removed from a fresh read
+    let keys: HashSet<String> = selected.iter().map(ProjectEntry::key).collect();
+    update_projects(&projects_file, |lines| {
+        lines.retain(|line| Line::project(line).is_none_or(|entry| !keys.contains(&entry.key())));
+    })?;
+    for entry in &selected {
+        println!("Deleted \"{}\" from .projects", entry.to_line());
+    }
+    Ok(())
+}
+
+/// Runs the main picker: recently used projects show up immediately while
+/// the rest are loaded in the background, then leaps to the selection.
+/// With a `query` the picker starts filtered by it and a single match is
+/// leapt to straight away; `first` leaps to the best match without a picker.
+/// Pressing [`crate::picker::CREATE_KEY`] in the picker creates a project named after the
+/// query with `scaffold` and leaps there.
+///
+/// # Errors
+/// Returns an error if nothing is selected or the session cannot be reached.
+pub fn execution<M>(
+    servers: &Servers<M>,
+    query: Option<&str>,
+    first: bool,
+    scaffold: &Scaffold,
+) -> Result<()>
+where
+    M: Multiplexer + Clone + Send + 'static,
+{
+    let cache_file = paths()?.cache_file.clone();
+    touch_file(&cache_file)?;
+    let selected_line = match query {
+        _ if first => best_match(
+            &picker_entries(&cache_file, servers)?,
+            query.unwrap_or_default(),
+        )?,
+        Some(query) => select_with_query(&picker_entries(&cache_file, servers)?, query)?,
+        None => {
+            let choice = stream_picker(&cache_file, servers)?;
+            if choice.create && !choice.query.trim().is_empty() {
+                return create_and_leap(&choice.query, scaffold, servers);
+            }
+            choice.selected
+        }
+    };
+    let (selected_str, server) = servers.parse_entry(&selected_line);
+    if selected_str.is_empty() {
+        return Err(Error::NoSelection);
+    }
+    let project = find_selection(selected_str, &load_picker_projects(servers)?)?;
+    if let Err(e) = update_cache(paths()?, &project.shortened_path) {
+        eprintln!("Cleanup failed: {e}");
+    }
+    servers.leap(&project, server)
+}
+
+/// Creates the project `name` with `scaffold`, adds it to the projects
+/// file and leaps to it on the primary server, in a session laid out by
+/// its template. An existing directory is used as it is, with a warning
+/// when that leaves a template unapplied.
+///
+/// # Errors
+/// Returns an error if the project cannot be set up or reached.
+pub fn create_and_leap<M: Multiplexer>(
+    name: &str,
+    scaffold: &Scaffold,
+    servers: &Servers<M>,
+) -> Result<()> {
+    let created = scaffold.create(name)?;
+    let project = Project::new(&created.dir.to_string_lossy());
+    add_entry(&project, None)?;
+    if created.created {
+        println!("Created \"{}\"", project.shortened_path);
+    } else {
+        println!("Using existing \"{}\"", project.shortened_path);
+        if let Some(template) = &scaffold.template {
+            eprintln!(
+                "tmux-leap: \"{}\" already exists, so template \"{template}\" was not applied",
+                project.shortened_path
+            );
+        }
+    }
+    leap_to_new(&project, &created.windows, servers)
+}
+
+/// Clones `url` into `root`, in a `host/path` directory like
+/// `github.com/owner/repo`, adds it to the projects file and leaps to it on
+/// the primary server. A repository that was cloned before is reused.
+///
+/// # Errors
+/// Returns an error if the URL has no usable path, the clone fails or the
+/// session cannot be reached.
+pub fn clone_and_leap<M: Multiplexer>(url: &str, root: &Path, servers: &Servers<M>) -> Result<()> {
+    let remote = Remote::parse(url)?;
+    let dir = root.join(&remote.dir);
+    let project = Project::new(&dir.to_string_lossy());
+    if dir.is_dir() {
+        println!("Already cloned to \"{}\"", project.shortened_path);
+    } else {
+        clone(&remote.url, &dir)?;
+        println!("Cloned to \"{}\"", project.shortened_path);
+    }
+    add_entry(&project, None)?;
+    leap_to_new(&project, &[], servers)
+}
+
+/// Checks out `branch` of the repository in the current directory into a
+/// worktree next to it, named like `app@branch`, and leaps to it. The
+/// worktree shows up in the picker through its repository's entry.
+///
+/// # Errors
+/// Returns an error if the current directory isn't in a git repository,
+/// the worktree cannot be added or the session cannot be reached.
+pub fn add_worktree<M: Multiplexer>(branch: &str, servers: &Servers<M>) -> Result<()> {
+    let current_dir = env::current_dir().map_err(|e| Error::io(".", e))?;
+    let repo = main_worktree(&current_dir)?;
+    let repo_name = repo.file_name().unwrap_or_default().to_string_lossy();
+    let dir = repo.with_file_name(format!("{repo_name}@{}", branch.replace('/', "-")));
+    git::add_worktree(&repo, &dir, branch)?;
+    let project = Project::new(&dir.to_string_lossy());
+    println!("Added worktree \"{}\" for {branch}", project.shortened_path);
+    leap_to_new(&project, &[], servers)
+}
+
+/// Removes the worktree of `branch` from the repository in the current
+/// directory and kills its session.
+///
+/// # Errors
+/// Returns an error if there is no such worktree, or git refuses to remove
+/// it because it has changes and `force` isn't set.
+pub fn remove_worktree(branch: &str, force: bool, mux: &dyn Multiplexer) -> Result<()> {
+    let current_dir = env::current_dir().map_err(|e| Error::io(".", e))?;
+    let repo = main_worktree(&current_dir)?;
+    let worktree = linked_worktrees(&repo)
+        .into_iter()
+        .find(|worktree| worktree.branch == branch)
+        .ok_or_else(|| Error::Git {
+            action: "worktree".to_string(),
+            message: format!("{} has no worktree for {branch}", repo.display()),
+        })?;
+    let project = Project::new(&worktree.dir.to_string_lossy());
+    git::remove_worktree(&repo, &worktree.dir, force)?;
+    println!(
+        "Removed worktree \"{}\" for {branch}",
+        project.shortened_path
+    );
+    if mux.session_exists(&project.tmux_display_path)? {
+        mux.kill_session(&project.tmux_display_path)?;
+    }
+    Ok(())
+}
+
+/// Leaps to a project that was just added, recording it as used and
+/// opening its session with `windows` if there are any.
+fn leap_to_new<M: Multiplexer>(
+    project: &Project,
+    windows: &[Window],
+    servers: &Servers<M>,
+) -> Result<()> {
+    if let Err(e) = update_cache(paths()?, &project.shortened_path) {
+        eprintln!("Cleanup failed: {e}");
+    }
+    let mux = &servers.primary().mux;
+    if !windows.is_empty() && !mux.session_exists(&project.tmux_display_path)? {
+        open_session(
+            mux,
+            &project.tmux_display_path,
+            &project.expanded_path,
+            windows,
+        )?;
+    }
+    servers.leap(project, servers.primary())
+}
+
+/// Shows the recently used projects in fzf straight away and streams the
+/// rest in from the background once they are loaded. Their git statuses
+/// are read after that and fill in the picker when they are all known, so
+/// slow repositories don't hold up the list. Returns what the user chose.
+fn stream_picker<M>(cache_file: &Path, servers: &Servers<M>) -> Result<PickerChoice>
+where
+    M: Multiplexer + Clone + Send + 'static,
+{
+    let statuses_dir = TempDir::new().map_err(|e| Error::io(env::temp_dir(), e))?;
+    let statuses_file = statuses_dir.path().join("statuses");
+    let mut fzf_process = start_fzf(&statuses_file)?;
+    let Some(mut fzf_stdin) = fzf_process.stdin.take() else {
+        return wait_for_fzf_selection(fzf_process);
+    };
+    let current = servers.current().unwrap_or_else(|| servers.primary());
+    let mut seen_items = HashSet::new();
+    let cached = get_cached_projects(cache_file, &current.mux)?;
+    // fzf may have exited already, which waiting for it tells
+    let mut shown =
+        write_entries(&mut fzf_stdin, &cached, servers, &mut seen_items).unwrap_or_default();
+    let producer_servers = servers.clone();
+    thread::spawn(move || {
+        // Errors surface again when the selection is resolved, so the
+        // producer just stops adding entries rather than printing over the
+        // picker
+        let rest = load_picker_projects(&producer_servers).and_then(|projects| {
+            write_entries(
+                &mut fzf_stdin,
+                &projects,
+                &producer_servers,
+                &mut seen_items,
+            )
+        });
+        // Closing stdin tells fzf every entry is in
+        drop(fzf_stdin);
+        shown.extend(rest.unwrap_or_default());
+        // fzf waits for the statuses file, so it is written even when
+        // loading failed
+        let _ = write_statuses(&shown, &statuses_file);
+    });
+    wait_for_fzf_selection(fzf_process)
+}
+
+/// Every picker entry in the order the picker shows them, loaded up front
+/// for when fzf has to see them all before it can choose.
+fn picker_entries<M: Multiplexer>(cache_file: &Path, servers: &Servers<M>) -> Result<Vec<String>> {
+    let current = servers.current().unwrap_or_else(|| servers.primary());
+    let mut entries = servers.entries(&get_cached_projects(cache_file, &current.mux)?)?;
+    let mut seen: HashSet<String> = entries.iter().cloned().collect();
+    for entry in servers.entries(&load_picker_projects(servers)?)? {
+        if seen.insert(entry.clone()) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Every existing project of the primary server plus the sessions of the
+/// other servers.
+fn load_picker_projects<M: Multiplexer>(servers: &Servers<M>) -> Result<Vec<Project>> {
+    let mut projects = load_and_filter_projects(&servers.primary().mux)?;
+    let mut seen: HashSet<String> = projects.iter().map(|p| p.canonical_path.clone()).collect();
+    for project in servers.secondary_sessions()?.filter_exists() {
+        if seen.insert(project.canonical_path.clone()) {
+            projects.push(project);
+        }
+    }
+    Ok(projects)
+}
+
+/// Finds the project for the picker entry `selected_str`, preferring the
+/// matching entry from `projects` and falling back to any existing directory.
+///
+/// # Errors
+/// Returns an error if the entry isn't a directory.
+pub fn find_selection(selected_str: &str, projects: &[Project]) -> Result<Project> {
+    if let Some(project) = projects.iter().find(|p| p.to_fzf_display() == selected_str) {
+        return Ok(project.clone());
+    }
+    let selected_project = Project::new(selected_str);
+    match projects
+        .iter()
+        .find(|p| p.canonical_path == selected_project.canonical_path)
+    {
+        Some(project) => Ok(project.clone()),
+        None if selected_project.exists() => Ok(selected_project),
+        None => Err(Error::NotADirectory(selected_project.expanded_path)),
+    }
+}
+
+/// Prints every project, including `--depth` expansions and sessions, or
+/// only those with `tag`.
+///
+/// # Errors
+/// Returns an error if the projects or sessions cannot be loaded.
+pub fn list_projects(format: OutputFormat, tag: Option<&str>, mux: &dyn Multiplexer) -> Result<()> {
+    let mut projects = get_projects(mux)?;
+    if let Some(tag) = tag {
+        projects.retain(|project| project.tags.iter().any(|t| t == tag));
+    }
+    print_records(&build_records(&projects, mux)?, format)
+}
+
+/// Prints the entries of the projects file as written, or for structured
+/// formats with its includes resolved.
+///
+/// # Errors
+/// Returns an error if the sessions cannot be listed.
+pub fn status_projects(format: OutputFormat, mux: &dyn Multiplexer) -> Result<()> {
+    let projects_file = paths()?.projects_file.clone();
+    if format == OutputFormat::Plain {
+        for line in read_lines(&projects_file).unwrap_or_default() {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+    let mut projects = Vec::new();
+    for entry in read_entries(&projects_file)? {
+        projects.extend(entry_projects(&entry)?);
+    }
+    print_records(&build_records(&projects, mux)?, format)
+}
+
+/// A depth given on the command line or at the prompt, where `none` or
+/// nothing removes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthSetting(pub Option<u32>);
+
+impl FromStr for DepthSetting {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.trim() {
+            "" | "none" => Ok(Self(None)),
+            depth => depth
+                .parse()
+                .map(|depth| Self(Some(depth)))
+                .map_err(|_| format!("invalid depth \"{depth}\": expected a number or none")),
+        }
+    }
+}
+
+impl fmt::Display for DepthSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(depth) => write!(f, "{depth}"),
+            None => write!(f, "none"),
+        }
+    }
+}
+
+/// Sets or removes the `--depth` of the entry for `dir`, or of a project
+/// chosen with fzf. Without `depth` it is read from stdin.
+///
+/// # Errors
+/// Returns an error if nothing is selected, the entry doesn't exist, the
+/// depth is invalid or the file cannot be updated.
+pub fn set_depth(dir: Option<&str>, depth: Option<DepthSetting>) -> Result<()> {
+    let projects_file = paths()?.projects_file.clone();
+    let selected = match dir {
+        Some(dir) => entry_for(&read_lines(&projects_file).unwrap_or_default(), dir)?,
+        None => {
+            let selected_str = select_with_fzf(&project_lines(&projects_file))?;
+            Line::project(&selected_str).ok_or(Error::NoSelection)?
+        }
+    };
+    let depth = match depth {
+        Some(depth) => depth,
+        None => {
+            println!(
+                "Set depth for {}: (Press Enter to remove depth, Ctrl+C to cancel)",
+                selected.to_line()
+            );
+            let mut depth_input = String::new();
+            std::io::stdin()
+                .read_line(&mut depth_input)
+                .map_err(|e| Error::io("<stdin>", e))?;
+            depth_input.parse().map_err(Error::Config)?
+        }
+    };
+    if depth.0.is_some() && selected.name.is_some() {
+        return Err(Error::Config(format!(
+            "{} has a --name, which needs a single directory",
+            selected.dir
+        )));
+    }
+    // Entries stay where they are, so manual ordering and comments survive
+    update_projects(&projects_file, |lines| {
+        if let Some((index, mut entry)) = find_entry(lines, &selected.key()) {
+            entry.depth = depth.0;
+            lines[index] = entry.to_line();
+        } else {
+            lines.push(
+                ProjectEntry {
+                    depth: depth.0,
+                    ..selected.clone()
+                }
+                .to_line(),
+            );
+        }
+    })?;
+    println!("Set depth for \"{}\" to {depth}", selected.dir);
+    Ok(())
+}
+
+/// Removes entries for directories that no longer exist from the projects
+/// file and the cache, and with `kill_sessions` also kills sessions whose
+/// directory is gone. The current session is never killed.
+///
+/// # Errors
+/// Returns an error if a file cannot be updated or a session killed.
+pub fn prune(dry_run: bool, kill_sessions: bool, mux: &dyn Multiplexer) -> Result<()> {
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    let projects_file = paths()?.projects_file.clone();
+    // Patterns may match nothing for a while and entries with unset
+    // variables may be meant for another machine, so both are kept
+    let dead = prune_lines(&projects_file, dry_run, true, |line| {
+        Line::project(line).is_some_and(|entry| {
+            !is_pattern(&entry.dir)
+                && expand_entry(&entry.dir)
+                    .is_ok_and(|projects| !projects.iter().any(Project::exists))
+        })
+    })?;
+    for line in &dead {
+        println!("{verb} \"{line}\" from .projects");
+    }
+
+    let cache_file = paths()?.cache_file.clone();
+    let dead = prune_lines(&cache_file, dry_run, false, |line| {
+        !Project::new(line).exists()
+    })?;
+    for line in &dead {
+        println!("{verb} \"{line}\" from .projects_cache");
+    }
+
+    if kill_sessions {
+        let current_session = mux.current_session()?;
+        for session in mux.sessions()? {
+            if Path::new(&session.path).is_dir() {
+                continue;
+            }
+            if current_session.as_ref() == Some(&session.name) {
+                println!("Keeping current session \"{}\"", session.name);
+            } else if dry_run {
+                println!("Would kill session \"{}\"", session.name);
+            } else {
+                mux.kill_session(&session.name)?;
+                println!("Killed session \"{}\"", session.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the lines of `file` that `is_dead`, returning them. With
+/// `dry_run` the file is left alone, and with `keep_history` its previous
+/// version is saved for `undo`.
+///
+/// # Errors
+/// Returns an error if the file cannot be updated.
+fn prune_lines(
+    file: &Path,
+    dry_run: bool,
+    keep_history: bool,
+    is_dead: impl Fn(&str) -> bool,
+) -> Result<Vec<String>> {
+    let is_dead = |line: &String| is_dead(line);
+    if dry_run {
+        let lines = read_lines(file).unwrap_or_default();
+        return Ok(lines.into_iter().filter(is_dead).collect());
+    }
+    let prune = |lines: &mut Vec<String>| {
+        let (dead, alive) = std::mem::take(lines).into_iter().partition(is_dead);
+        *lines = alive;
+        dead
+    };
+    if keep_history {
+        update_projects(file, prune)
+    } else {
+        update_lines(file, prune)
+    }
+}
+
+/// Prints the result of every [`diagnose`] check with suggestions.
+///
+/// # Errors
+/// Returns an error if any check failed.
+pub fn doctor(tmux: &Tmux) -> Result<()> {
+    let findings = diagnose(tmux);
+    for finding in &findings {
+        println!("{finding}");
+    }
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(Error::Unhealthy(errors));
+    }
+    Ok(())
+}
+
+/// Opens the projects file in `$EDITOR`.
+///
+/// # Errors
+/// Returns an error if the editor cannot be launched.
+pub fn edit_projects_file() -> Result<()> {
+    let projects_file = paths()?.projects_file.clone();
+    touch_file(&projects_file)?;
+    let previous = read_lines(&projects_file).unwrap_or_default();
+    let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let status = Command::new(&editor)
+        .arg(&projects_file)
+        .status()
+        .map_err(|e| Error::spawn(&editor, e))?;
+    if !status.success() {
+        eprintln!("Editor exited with non-zero status: {status}");
+    }
+    if read_lines(&projects_file).unwrap_or_default() != previous {
+        history::save(&paths()?.history_dir, &previous)?;
+    }
+    Ok(())
+}
+
+/// Lists the saved versions of the projects file, newest first, with what
+/// undoing to each one would bring back (`+`) and remove (`-`).
+///
+/// # Errors
+/// Returns an error if the history cannot be read.
+pub fn show_history() -> Result<()> {
+    let snapshots = history::snapshots(&paths()?.history_dir)?;
+    if snapshots.is_empty() {
+        println!("No earlier versions of .projects");
+        return Ok(());
+    }
+    let now = unix_now();
+    let mut newer = read_lines(paths()?.projects_file.clone()).unwrap_or_default();
+    for snapshot in snapshots.iter().rev() {
+        let lines = snapshot.lines()?;
+        let (restored, removed) = history::changes(&lines, &newer);
+        let summary: Vec<String> = restored
+            .iter()
+            .map(|line| format!("+{line}"))
+            .chain(removed.iter().map(|line| format!("-{line}")))
+            .collect();
+        let summary = if summary.is_empty() {
+            "reorders entries".to_string()
+        } else {
+            summary.join(", ")
+        };
+        println!(
+            "{}\t{}\t{summary}",
+            snapshot.id,
+            history::age(snapshot.saved_at, now)
+        );
+        newer = lines;
+    }
+    Ok(())
+}
+
+/// Restores the projects file to saved version `id`, or the newest one,
+/// saving the current version first.
+///
+/// # Errors
+/// Returns an error if there is no such version or the file cannot be
+/// written.
+pub fn undo(id: Option<u64>) -> Result<()> {
+    let snapshot = history::restore(&paths()?.history_dir, &paths()?.projects_file, id)?;
+    println!(
+        "Restored .projects to version {} from {}",
+        snapshot.id,
+        history::age(snapshot.saved_at, unix_now())
+    );
+    Ok(())
+}
+
+/// Lists the profiles, marking the active one with `*`.
+///
+/// # Errors
+/// Returns an error if the home directory cannot be determined.
+pub fn show_profiles() -> Result<()> {
+    let active = paths()?.profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+    let mut profiles = list_profiles()?;
+    profiles.retain(|profile| profile != DEFAULT_PROFILE);
+    profiles.insert(0, DEFAULT_PROFILE.to_string());
+    if !profiles.iter().any(|profile| profile == active) {
+        profiles.push(active.to_string());
+    }
+    for profile in profiles {
+        let marker = if profile == active { '*' } else { ' ' };
+        println!("{marker} {profile}");
+    }
+    Ok(())
+}
+
+/// Prints the candidates in the order the picker would show them.
+///
+/// # Errors
+/// Returns an error if the projects or sessions cannot be loaded.
+pub fn list_candidates(format: OutputFormat, mux: &dyn Multiplexer) -> Result<()> {
+    print_records(&build_records(&get_candidates(mux)?, mux)?, format)
+}
+
+/// Prints the session name and path selecting `entry`, a picker entry or
+/// a directory, would use.
+///
+/// # Errors
+/// Returns an error if `entry` is neither.
+pub fn resolve_entry(entry: &str, format: OutputFormat, mux: &dyn Multiplexer) -> Result<()> {
+    let projects = load_and_filter_projects(mux)?;
+    let project = match projects.iter().find(|p| p.to_fzf_display() == entry) {
+        Some(project) => project.clone(),
+        None => {
+            let project = Project::new(&absolute_path(entry, true)?);
+            if !project.exists() {
+                return Err(Error::NotADirectory(project.expanded_path));
+            }
+            projects
+                .into_iter()
+                .find(|p| p.canonical_path == project.canonical_path)
+                .unwrap_or(project)
+        }
+    };
+    if format == OutputFormat::Plain {
+        println!("{}\t{}", project.tmux_display_path, project.expanded_path);
+    } else {
+        print_records(&build_records(&[project], mux)?, format)?;
+    }
+    Ok(())
+}
+
+/// Leaps straight to `dir`. From outside tmux the attached client replaces
+/// this process so it persists.
+///
+/// # Errors
+/// Returns an error if `dir` isn't a directory or the session cannot be
+/// reached.
+pub fn goto_project(dir: &str, mux: &dyn Multiplexer) -> Result<()> {
+    let project = Project::new(&absolute_path(dir, true)?);
+
+    // Check if the directory exists
+    if !project.exists() {
+        return Err(Error::NotADirectory(project.expanded_path));
+    }
+
+    if mux.is_inside() {
+        // Inside tmux: just switch/attach normally
+        project.attach(mux)
+    } else {
+        // Outside tmux: ensure session exists, then attach using exec to persist
+        let tmux_session_name = &project.session_name(mux)?;
+
+        if !mux.session_exists(tmux_session_name)? {
+            mux.create_session(tmux_session_name, &project.expanded_path)?;
+        }
+
+        // Replace current process with tmux attach (this persists)
+        mux.attach_session_exec(tmux_session_name, &project.expanded_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiplexer::fake::FakeMultiplexer;
+
+    #[test]
+    fn goto_outside_tmux_creates_and_attaches() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        let mux = FakeMultiplexer::new();
+
+        goto_project(path, &mux).unwrap();
+
+        let session = Project::new(path).tmux_display_path;
+        assert_eq!(mux.current(), Some(session.clone()));
+        assert_eq!(mux.session_list()[0].name, session);
+    }
+
+    #[test]
+    fn goto_inside_tmux_switches_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        let mux = FakeMultiplexer::new().inside("main");
+
+        goto_project(path, &mux).unwrap();
+
+        assert_eq!(mux.current(), Some(Project::new(path).tmux_display_path));
+        assert_eq!(mux.session_list().len(), 2);
+    }
+
+    #[test]
+    fn goto_missing_directory_fails_without_touching_tmux() {
+        let mux = FakeMultiplexer::new();
+
+        let result = goto_project("/definitely/not/here", &mux);
+
+        assert!(matches!(result, Err(Error::NotADirectory(_))));
+        assert!(mux.session_list().is_empty());
+    }
+
+    #[test]
+    fn selection_prefers_known_project_and_reuses_its_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        let project = Project::new(path);
+        let mux = FakeMultiplexer::new()
+            .with_session(&project.tmux_display_path, path)
+            .inside("main");
+
+        find_selection(&project.shortened_path, std::slice::from_ref(&project))
+            .unwrap()
+            .attach(&mux)
+            .unwrap();
+
+        assert_eq!(mux.current(), Some(project.tmux_display_path));
+        assert_eq!(mux.session_list().len(), 2);
+    }
+
+    #[test]
+    fn selection_of_vanished_directory_is_an_error() {
+        let result = find_selection("/definitely/not/here", &[]);
+
+        assert!(matches!(result, Err(Error::NotADirectory(_))));
+    }
+}
@@ -0,0 +1,479 @@
+use crate::files::{paths, read_lines};
+use crate::multiplexer::Multiplexer;
+use crate::project::{expand_entry, get_projects, is_pattern, Project};
+use crate::projects_file::{include_path, Line};
+use crate::tmux::Tmux;
+use regex::Regex;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The oldest tmux with `display-popup`, used by the recommended keybindings.
+pub const POPUP_TMUX_VERSION: (u32, u32) = (3, 2);
+
+/// The oldest fzf with the `load` event and `reload-sync` action the picker
+/// fills in git statuses with.
+pub const PICKER_FZF_VERSION: (u32, u32) = (0, 36);
+
+/// How many directories a `--depth` root may expand to before the picker
+/// gets slow to fill.
+pub const EXCESSIVE_DEPTH_RESULTS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// The result of one check, with a suggestion for fixing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Ok,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn error(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{label:<8} {}", self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n{:<8} hint: {hint}", "")?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs every check against `tmux` and the files in the home directory.
+#[must_use]
+pub fn diagnose(tmux: &Tmux) -> Vec<Finding> {
+    let mut findings = vec![check_tmux(), check_fzf()];
+    findings.extend(check_nesting(tmux));
+
+    let Ok(paths) = paths() else {
+        findings.push(Finding::error("unable to find home directory", "set $HOME"));
+        return findings;
+    };
+    if let Some(profile) = &paths.profile {
+        findings.push(Finding::ok(format!("using profile {profile}")));
+    }
+    let projects_file = &paths.projects_file;
+    let lines = match read_lines(projects_file) {
+        Ok(lines) => lines,
+        Err(_) => {
+            findings.push(Finding::warning(
+                format!("{} does not exist", projects_file.display()),
+                "add a project with `tmux-leap add`",
+            ));
+            return findings;
+        }
+    };
+    let mut file_findings = check_projects_file(projects_file, &lines);
+    file_findings.extend(check_included_files(projects_file, &lines));
+    let file_is_valid = file_findings.iter().all(|f| f.severity != Severity::Error);
+    findings.extend(file_findings);
+
+    // Expanding depth roots needs a well-formed file
+    if file_is_valid {
+        match get_projects(tmux) {
+            Ok(projects) => {
+                findings.extend(check_depth_roots(&projects));
+                findings.extend(check_session_names(&projects));
+            }
+            Err(e) => findings.push(Finding::error(
+                format!("unable to load projects: {e}"),
+                "fix the error above and run doctor again",
+            )),
+        }
+    }
+
+    let cache_lines = read_lines(&paths.cache_file).unwrap_or_default();
+    findings.extend(check_cache(&paths.cache_file, &cache_lines));
+    findings
+}
+
+/// Parses the major and minor version out of `tmux -V` output such as
+/// `tmux 3.3a` or `tmux next-3.4`, or `fzf --version` output such as
+/// `0.44.1 (brew)`.
+#[must_use]
+pub fn parse_version(output: &str) -> Option<(u32, u32)> {
+    let re = Regex::new(r"(\d+)\.(\d+)").unwrap();
+    let captures = re.captures(output)?;
+    Some((captures[1].parse().ok()?, captures[2].parse().ok()?))
+}
+
+/// Gets the first line a program prints for `--version`-style `flag`, or
+/// `None` if it isn't installed.
+fn program_version(program: &str, flag: &str) -> Option<String> {
+    let output = Command::new(program).arg(flag).output().ok()?;
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .map_or_else(
+                || "unknown version".to_string(),
+                |line| line.trim().to_string(),
+            ),
+    )
+}
+
+fn check_tmux() -> Finding {
+    let Some(version) = program_version("tmux", "-V") else {
+        return Finding::error(
+            "tmux is not installed",
+            "install tmux 3.2 or newer from your package manager",
+        );
+    };
+    match parse_version(&version) {
+        Some(parsed) if parsed < POPUP_TMUX_VERSION => Finding::warning(
+            format!("{version} is older than 3.2"),
+            "popups used by the recommended keybindings need tmux 3.2, \
+             and tmux-leap falls back to slower per-command calls",
+        ),
+        // Development builds like `tmux master` carry no version
+        _ => Finding::ok(version),
+    }
+}
+
+fn check_fzf() -> Finding {
+    let Some(version) = program_version("fzf", "--version") else {
+        return Finding::error(
+            "fzf is not installed",
+            "install fzf 0.36 or newer from your package manager or \
+             https://github.com/junegunn/fzf",
+        );
+    };
+    match parse_version(&version) {
+        Some(parsed) if parsed < PICKER_FZF_VERSION => Finding::error(
+            format!("fzf {version} is older than 0.36"),
+            "the picker needs fzf 0.36 or newer; install it from \
+             https://github.com/junegunn/fzf",
+        ),
+        _ => Finding::ok(format!("fzf {version}")),
+    }
+}
+
+/// Reports whether tmux-leap runs inside a client of the selected server,
+/// of another server, or inside a client that is itself nested.
+fn check_nesting(tmux: &Tmux) -> Vec<Finding> {
+    if env::var("TMUX").is_err() {
+        return vec![Finding::ok(
+            "not inside tmux; leaping attaches a new client",
+        )];
+    }
+    if !tmux.is_inside() {
+        return vec![Finding::warning(
+            format!(
+                "inside a client of another tmux server than \"{}\"",
+                tmux.name()
+            ),
+            "leaping would attach a nested client; run tmux-leap from the selected server \
+             or pass its --socket-name/--socket-path",
+        )];
+    }
+    match tmux.client_terminal() {
+        Ok(Some(terminal)) if terminal.starts_with("tmux") || terminal.starts_with("screen") => {
+            vec![Finding::warning(
+                format!("this tmux client runs inside another multiplexer (TERM={terminal})"),
+                "both use the same prefix key by default; change one with `set -g prefix`",
+            )]
+        }
+        Ok(_) => vec![Finding::ok("inside tmux")],
+        Err(e) => vec![Finding::warning(
+            format!("unable to inspect the tmux client: {e}"),
+            "check that the tmux server is responding",
+        )],
+    }
+}
+
+/// Validates the projects file line by line: malformed entries,
+/// duplicates, directories that don't exist and missing included files.
+#[must_use]
+pub fn check_projects_file(projects_file: &Path, lines: &[String]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut seen = HashMap::new();
+    let mut projects = 0;
+    for (index, line) in lines.iter().enumerate() {
+        let location = format!("{}:{}", projects_file.display(), index + 1);
+        let entry = match Line::parse(line) {
+            Ok(Line::Blank | Line::Comment) => continue,
+            Ok(Line::Include(targets)) => {
+                for target in targets {
+                    let included = include_path(&target, projects_file);
+                    if !included.is_file() {
+                        findings.push(Finding::warning(
+                            format!(
+                                "{location}: included file {} does not exist",
+                                included.display()
+                            ),
+                            "create it or remove it from the include line with `tmux-leap edit`",
+                        ));
+                    }
+                }
+                continue;
+            }
+            Ok(Line::Project(entry)) => entry,
+            Err(e) => {
+                findings.push(Finding::error(
+                    format!("{location}: {e}"),
+                    "fix the entry with `tmux-leap edit`",
+                ));
+                continue;
+            }
+        };
+        projects += 1;
+        let dir = entry.dir.as_str();
+        let project = match expand_entry(dir) {
+            Ok(matches) if is_pattern(dir) || matches.len() != 1 => {
+                if matches.is_empty() {
+                    findings.push(Finding::warning(
+                        format!("{location}: {dir} matches no directories"),
+                        "check the pattern with `tmux-leap edit`",
+                    ));
+                }
+                continue;
+            }
+            Ok(mut matches) => matches.remove(0),
+            Err(e) => {
+                findings.push(Finding::error(
+                    format!("{location}: {e}"),
+                    "fix the entry with `tmux-leap edit`; \
+                     a variable can get a default like ${WORK:-~/work}",
+                ));
+                continue;
+            }
+        };
+        if let Some(first) = seen.insert(project.canonical_path.clone(), index + 1) {
+            findings.push(Finding::warning(
+                format!("{location}: {dir} is already listed on line {first}"),
+                "remove one of the entries with `tmux-leap delete`",
+            ));
+        }
+        if !project.exists() {
+            findings.push(Finding::warning(
+                format!("{location}: {dir} is not a directory"),
+                "remove it with `tmux-leap prune` or create it",
+            ));
+        }
+    }
+    if findings.is_empty() {
+        findings.push(Finding::ok(format!(
+            "{} lists {projects} projects",
+            projects_file.display(),
+        )));
+    }
+    findings
+}
+
+/// The files the `include` lines of `file` name.
+fn included_files(file: &Path, lines: &[String]) -> Vec<PathBuf> {
+    lines
+        .iter()
+        .filter_map(|line| match Line::parse(line) {
+            Ok(Line::Include(targets)) => Some(targets),
+            _ => None,
+        })
+        .flatten()
+        .map(|target| include_path(&target, file))
+        .collect()
+}
+
+/// Runs [`check_projects_file`] on every file the projects file includes,
+/// directly or not, checking each one once.
+fn check_included_files(projects_file: &Path, lines: &[String]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut checked: HashSet<PathBuf> = fs::canonicalize(projects_file).into_iter().collect();
+    let mut pending: VecDeque<PathBuf> = included_files(projects_file, lines).into();
+    while let Some(file) = pending.pop_front() {
+        let Ok(canonical) = fs::canonicalize(&file) else {
+            continue;
+        };
+        let Ok(lines) = read_lines(&file) else {
+            continue;
+        };
+        if !checked.insert(canonical) {
+            continue;
+        }
+        pending.extend(included_files(&file, &lines));
+        findings.extend(check_projects_file(&file, &lines));
+    }
+    findings
+}
+
+/// Flags `--depth` roots that expand to so many directories that filling
+/// the picker gets slow.
+#[must_use]
+pub fn check_depth_roots(projects: &[Project]) -> Vec<Finding> {
+    let mut counts: Vec<(&str, u32, usize)> = Vec::new();
+    for source in projects.iter().filter_map(|p| p.depth_source.as_ref()) {
+        match counts.iter_mut().find(|(root, ..)| *root == source.root) {
+            Some((.., count)) => *count += 1,
+            None => counts.push((&source.root, source.depth, 1)),
+        }
+    }
+    counts
+        .into_iter()
+        .filter(|(.., count)| *count > EXCESSIVE_DEPTH_RESULTS)
+        .map(|(root, depth, count)| {
+            Finding::warning(
+                format!("{root} --depth {depth} expands to {count} directories"),
+                format!(
+                    "lower it with `tmux-leap set-depth` or list the subdirectories you use; \
+                     more than {EXCESSIVE_DEPTH_RESULTS} slow down the picker"
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Flags different directories that would get the same session name, so
+/// leaping to one ends up in the other's session.
+#[must_use]
+pub fn check_session_names(projects: &[Project]) -> Vec<Finding> {
+    let mut by_name: HashMap<&str, Vec<&str>> = HashMap::new();
+    for project in projects {
+        let paths = by_name.entry(&project.tmux_display_path).or_default();
+        if !paths.contains(&project.canonical_path.as_str()) {
+            paths.push(&project.canonical_path);
+        }
+    }
+    let mut collisions: Vec<(&str, Vec<&str>)> = by_name
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect();
+    collisions.sort_unstable();
+    collisions
+        .into_iter()
+        .map(|(name, paths)| {
+            Finding::warning(
+                format!(
+                    "session name \"{name}\" is shared by {}",
+                    paths.join(" and ")
+                ),
+                "session names use underscores for dots; rename one of the directories",
+            )
+        })
+        .collect()
+}
+
+/// Flags cache entries for directories that no longer exist.
+#[must_use]
+pub fn check_cache(cache_file: &Path, lines: &[String]) -> Vec<Finding> {
+    let mut seen = HashSet::new();
+    let stale: Vec<&str> = lines
+        .iter()
+        .map(String::as_str)
+        .filter(|path| seen.insert(*path))
+        .filter(|path| !Project::new(path).exists())
+        .collect();
+    if stale.is_empty() {
+        return Vec::new();
+    }
+    vec![Finding::warning(
+        format!(
+            "{} has {} entries for missing directories: {}",
+            cache_file.display(),
+            stale.len(),
+            stale.join(", ")
+        ),
+        "they are skipped by the picker; remove them with `tmux-leap prune`",
+    )]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| (*line).to_string()).collect()
+    }
+
+    #[test]
+    fn versions_ignore_suffixes() {
+        assert_eq!(parse_version("tmux 3.3a"), Some((3, 3)));
+        assert_eq!(parse_version("tmux next-3.4"), Some((3, 4)));
+        assert_eq!(parse_version("tmux master"), None);
+        assert!(parse_version("tmux 3.1c").unwrap() < POPUP_TMUX_VERSION);
+        assert_eq!(parse_version("0.44.1 (brew)"), Some((0, 44)));
+        assert!(parse_version("0.35.1 (d8a7c2e)").unwrap() < PICKER_FZF_VERSION);
+    }
+
+    #[test]
+    fn projects_file_problems_carry_line_numbers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        let findings = check_projects_file(
+            Path::new("p"),
+            &lines(&[
+                path,
+                &format!("{path} --depth 2"),
+                "/definitely/not/here",
+                "",
+                "# notes",
+                "~/x --depth",
+                "~/y --depth 99999999999",
+            ]),
+        );
+
+        let messages: Vec<&str> = findings.iter().map(|f| f.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                &format!("p:2: {path} is already listed on line 1"),
+                "p:3: /definitely/not/here is not a directory",
+                "p:6: --depth needs a value",
+                "p:7: invalid --depth \"99999999999\": number too large to fit in target type",
+            ]
+        );
+        assert_eq!(findings[2].severity, Severity::Error);
+    }
+
+    #[test]
+    fn colliding_session_names_are_reported_once() {
+        let projects = [
+            Project::new("/srv/a.b"),
+            Project::new("/srv/a_b"),
+            Project::new("/srv/a_b"),
+            Project::new("/srv/c"),
+        ];
+
+        let findings = check_session_names(&projects);
+
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].message.contains("/srv/a.b and /srv/a_b"));
+    }
+}
@@ -0,0 +1,114 @@
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use thiserror::Error;
+
+/// Process exit codes scripts can branch on.
+pub mod exit_code {
+    pub const FAILURE: i32 = 1;
+    pub const NO_SELECTION: i32 = 3;
+    pub const DEPENDENCY_MISSING: i32 = 4;
+    pub const TMUX: i32 = 5;
+    pub const CONFIG: i32 = 6;
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("no selection made")]
+    NoSelection,
+
+    #[error("`{program}` is not installed or not on $PATH")]
+    DependencyMissing { program: String },
+
+    #[error("tmux {action} failed: {message}")]
+    Tmux { action: String, message: String },
+
+    #[error("git {action} failed: {message}")]
+    Git { action: String, message: String },
+
+    #[error("{0}")]
+    Config(String),
+
+    #[error("directory \"{0}\" does not exist or is not a directory")]
+    NotADirectory(String),
+
+    #[error("\"{0}\" is not in the projects file")]
+    UnknownProject(String),
+
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+
+    #[error("failed to run `{program}`: {source}")]
+    Spawn { program: String, source: io::Error },
+
+    #[error("`{command}` failed with {status}")]
+    CommandFailed { command: String, status: ExitStatus },
+
+    #[error("doctor found {0} problem(s)")]
+    Unhealthy(usize),
+
+    #[error("{0}")]
+    History(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Maps a failure to spawn `program` to a missing dependency when the
+    /// binary could not be found.
+    #[must_use]
+    pub fn spawn(program: &str, source: io::Error) -> Self {
+        if source.kind() == io::ErrorKind::NotFound {
+            Self::DependencyMissing {
+                program: program.to_string(),
+            }
+        } else {
+            Self::Spawn {
+                program: program.to_string(),
+                source,
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Self::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    #[must_use]
+    pub fn tmux(action: &str, stderr: &[u8]) -> Self {
+        Self::Tmux {
+            action: action.to_string(),
+            message: String::from_utf8_lossy(stderr).trim().to_string(),
+        }
+    }
+
+    #[must_use]
+    pub fn git(action: &str, stderr: &[u8]) -> Self {
+        Self::Git {
+            action: action.to_string(),
+            message: String::from_utf8_lossy(stderr).trim().to_string(),
+        }
+    }
+
+    #[must_use]
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::NoSelection => exit_code::NO_SELECTION,
+            Self::DependencyMissing { .. } => exit_code::DEPENDENCY_MISSING,
+            Self::Tmux { .. } => exit_code::TMUX,
+            Self::Config(_) => exit_code::CONFIG,
+            Self::Git { .. }
+            | Self::NotADirectory(_)
+            | Self::UnknownProject(_)
+            | Self::Io { .. }
+            | Self::Spawn { .. }
+            | Self::CommandFailed { .. }
+            | Self::Unhealthy(_)
+            | Self::History(_) => exit_code::FAILURE,
+        }
+    }
+}
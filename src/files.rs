@@ -0,0 +1,475 @@
+use crate::error::{Error, Result};
+use crate::history::{self, HISTORY_DIR};
+use crate::project::Project;
+use dirs::home_dir;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tempfile::NamedTempFile;
+
+/// The projects file in the home directory, used when it already exists.
+pub const PROJECTS_FILE: &str = ".projects";
+/// The cache in the home directory, used alongside the legacy projects file.
+pub const CACHE_FILE: &str = ".projects_cache";
+const MAX_CACHE_ENTRIES: usize = 100;
+
+/// The directory below `$XDG_CONFIG_HOME` and `$XDG_STATE_HOME`.
+const APP_DIR: &str = "tmux-leap";
+/// The directory of profile projects files, and of their state.
+const PROFILES_DIR: &str = "profiles";
+/// The file next to the cache with the times its projects were last used.
+const LAST_USED_FILE: &str = "last-used";
+/// The file in the state directory locked while files change.
+const LOCK_FILE: &str = "lock";
+/// The profile name that selects the files used without a profile.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Resolves `file` relative to the home directory.
+///
+/// # Errors
+/// Returns an error if the home directory cannot be determined.
+pub fn get_home_path(file: &str) -> Result<PathBuf> {
+    let file = file.strip_prefix('~').unwrap_or(file);
+    home_dir()
+        .map(|home| home.join(file))
+        .ok_or_else(|| Error::Config("unable to find home directory".to_string()))
+}
+
+/// Where the projects file, the cache and the history live.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    /// The active profile, or `None` for the default files
+    pub profile: Option<String>,
+    pub projects_file: PathBuf,
+    /// The recently used projects, one path per line, most recent first
+    pub cache_file: PathBuf,
+    /// When the cached projects were last used, as `PATH\tUNIXTIME`
+    /// lines. Kept apart from the cache so older versions can still read it
+    pub last_used_file: PathBuf,
+    pub history_dir: PathBuf,
+    /// Locked while any of the other files change
+    pub lock_file: PathBuf,
+}
+
+impl Paths {
+    /// Resolves the locations from the environment.
+    ///
+    /// Without a profile the projects file is `config`, else
+    /// `$TMUX_LEAP_CONFIG`, else `~/.projects` if it exists, else
+    /// `$XDG_CONFIG_HOME/tmux-leap/projects`. The cache and history go in
+    /// `$TMUX_LEAP_STATE`, else next to a legacy `~/.projects` or
+    /// `~/.projects_cache` in the home directory, else in
+    /// `$XDG_STATE_HOME/tmux-leap`. Every profile and legacy setup shares
+    /// the lock file in the latter, or in `$TMUX_LEAP_STATE`, and legacy
+    /// setups keep their last-used times there too.
+    ///
+    /// When `profile` is `None` one is picked by [`detect_profile`]. A
+    /// profile keeps its projects in `$XDG_CONFIG_HOME/tmux-leap/profiles/NAME`
+    /// unless `config` says otherwise, and its cache and history in a
+    /// `profiles/NAME` directory of the state directory.
+    ///
+    /// # Errors
+    /// Returns an error if the home directory cannot be determined or the
+    /// profile name is invalid.
+    pub fn resolve(config: Option<PathBuf>, profile: Option<String>) -> Result<Self> {
+        let config = config.or_else(|| env_path("TMUX_LEAP_CONFIG"));
+        let profiles_dir = config_dir()?.join(PROFILES_DIR);
+        let profile = match profile {
+            Some(name) if name == DEFAULT_PROFILE => None,
+            Some(name) => Some(validate_profile(name)?),
+            None => detect_profile(&profiles_dir, &whoami::username(), &short_hostname()),
+        };
+        let Some(profile) = profile else {
+            return Self::resolve_default(config);
+        };
+        let state = state_dir()?;
+        let profile_state = state.join(PROFILES_DIR).join(&profile);
+        Ok(Self {
+            projects_file: config.unwrap_or_else(|| profiles_dir.join(&profile)),
+            cache_file: profile_state.join("cache"),
+            last_used_file: profile_state.join(LAST_USED_FILE),
+            history_dir: profile_state.join("history"),
+            lock_file: state.join(LOCK_FILE),
+            profile: Some(profile),
+        })
+    }
+
+    fn resolve_default(config: Option<PathBuf>) -> Result<Self> {
+        let legacy_projects = get_home_path(PROJECTS_FILE)?;
+        let legacy_cache = get_home_path(CACHE_FILE)?;
+        let projects_file = match config {
+            Some(config) => config,
+            None if legacy_projects.exists() => legacy_projects.clone(),
+            None => config_dir()?.join("projects"),
+        };
+        let state = state_dir()?;
+        if env_path("TMUX_LEAP_STATE").is_none()
+            && (projects_file == legacy_projects || legacy_cache.exists())
+        {
+            return Ok(Self {
+                profile: None,
+                projects_file,
+                cache_file: legacy_cache,
+                last_used_file: state.join(LAST_USED_FILE),
+                history_dir: get_home_path(HISTORY_DIR)?,
+                lock_file: state.join(LOCK_FILE),
+            });
+        }
+        Ok(Self {
+            profile: None,
+            projects_file,
+            cache_file: state.join("cache"),
+            last_used_file: state.join(LAST_USED_FILE),
+            history_dir: state.join("history"),
+            lock_file: state.join(LOCK_FILE),
+        })
+    }
+}
+
+/// Picks the profile for this machine: the first of `USER@HOST`, `HOST`
+/// and `USER` with a projects file in `profiles_dir`, so one set of
+/// dotfiles can carry the project lists of several machines.
+#[must_use]
+pub fn detect_profile(profiles_dir: &Path, user: &str, host: &str) -> Option<String> {
+    [format!("{user}@{host}"), host.to_string(), user.to_string()]
+        .into_iter()
+        .filter(|name| validate_profile(name.clone()).is_ok())
+        .find(|name| profiles_dir.join(name).is_file())
+}
+
+/// Every profile with a projects file, sorted by name.
+///
+/// # Errors
+/// Returns an error if the home directory cannot be determined.
+pub fn list_profiles() -> Result<Vec<String>> {
+    let profiles_dir = config_dir()?.join(PROFILES_DIR);
+    let mut profiles: Vec<String> = fs::read_dir(profiles_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter(|name| !name.starts_with('.'))
+                .collect()
+        })
+        .unwrap_or_default();
+    profiles.sort();
+    Ok(profiles)
+}
+
+/// The host name without its domain, or empty if it can't be found.
+fn short_hostname() -> String {
+    whoami::fallible::hostname()
+        .map(|host| host.split('.').next().unwrap_or_default().to_string())
+        .unwrap_or_default()
+}
+
+/// Profile names become file names, so they can't hold a path.
+fn validate_profile(name: String) -> Result<String> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') || name.starts_with('@') {
+        return Err(Error::Config(format!("invalid profile name {name:?}")));
+    }
+    Ok(name)
+}
+
+/// `$XDG_CONFIG_HOME/tmux-leap`, where profiles and templates live.
+///
+/// # Errors
+/// Returns an error if the home directory cannot be determined.
+pub fn config_dir() -> Result<PathBuf> {
+    Ok(xdg_dir("XDG_CONFIG_HOME", ".config")?.join(APP_DIR))
+}
+
+/// `$TMUX_LEAP_STATE`, else `$XDG_STATE_HOME/tmux-leap`.
+fn state_dir() -> Result<PathBuf> {
+    match env_path("TMUX_LEAP_STATE") {
+        Some(state) => Ok(state),
+        None => Ok(xdg_dir("XDG_STATE_HOME", ".local/state")?.join(APP_DIR)),
+    }
+}
+
+/// A non-empty path from the environment variable `name`.
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// An XDG base directory, falling back to `default` in the home directory
+/// as the spec requires for unset or relative values.
+fn xdg_dir(name: &str, default: &str) -> Result<PathBuf> {
+    match env_path(name) {
+        Some(dir) if dir.is_absolute() => Ok(dir),
+        _ => get_home_path(default),
+    }
+}
+
+static PATHS: OnceLock<Paths> = OnceLock::new();
+
+/// Fixes the locations for the rest of the process. Has no effect once
+/// they've been set or used.
+pub fn set_paths(paths: Paths) {
+    let _ = PATHS.set(paths);
+}
+
+/// The locations set by [`set_paths`], or resolved from the environment.
+///
+/// # Errors
+/// Returns an error if the home directory cannot be determined.
+pub fn paths() -> Result<&'static Paths> {
+    if let Some(paths) = PATHS.get() {
+        return Ok(paths);
+    }
+    let paths = Paths::resolve(None, None)?;
+    Ok(PATHS.get_or_init(|| paths))
+}
+
+/// Creates the directory `path` goes in, for files in XDG directories that
+/// may not exist yet.
+fn create_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+/// Creates `path` if it doesn't exist yet, leaving existing contents alone.
+///
+/// # Errors
+/// Returns an error if the file cannot be created.
+pub fn touch_file(path: &Path) -> Result<()> {
+    create_parent(path).map_err(|e| Error::io(path, e))?;
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .map_err(|e| Error::io(path, e))?;
+    Ok(())
+}
+
+/// Reads every line of a file.
+///
+/// # Errors
+/// Returns an error if the file cannot be read.
+pub fn read_lines<P>(filename: P) -> std::io::Result<Vec<String>>
+where
+    P: AsRef<std::path::Path>,
+{
+    let file = File::open(filename)?;
+    let buf = BufReader::new(file);
+    buf.lines().collect()
+}
+
+/// Replaces the contents of a file with `lines`. The new contents are
+/// written to a temporary file next to it which is then renamed over it,
+/// so readers see either the old or the new file, never a partial one.
+///
+/// # Errors
+/// Returns an error if the file cannot be written.
+pub fn write_lines<P>(filename: P, lines: &[String]) -> Result<()>
+where
+    P: AsRef<std::path::Path>,
+{
+    // Write through symlinks, as with dotfile managers, instead of
+    // replacing them
+    let path = fs::canonicalize(&filename).unwrap_or_else(|_| filename.as_ref().to_path_buf());
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let write = || -> std::io::Result<()> {
+        create_parent(&path)?;
+        let mut file = NamedTempFile::new_in(dir)?;
+        if let Ok(metadata) = fs::metadata(&path) {
+            file.as_file().set_permissions(metadata.permissions())?;
+        }
+        {
+            let mut writer = BufWriter::new(file.as_file_mut());
+            for line in lines {
+                writeln!(writer, "{line}")?;
+            }
+            writer.flush()?;
+        }
+        file.as_file().sync_all()?;
+        file.persist(&path)?;
+        Ok(())
+    };
+    write().map_err(|e| Error::io(&path, e))
+}
+
+/// An exclusive advisory lock on the [`Paths::lock_file`], released when
+/// dropped.
+///
+/// The lock has a file of its own because [`write_lines`] replaces the
+/// files it guards.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+/// Waits for the exclusive lock taken while tmux-leap changes its files.
+///
+/// # Errors
+/// Returns an error if the lock file cannot be opened or locked.
+pub fn lock_files() -> Result<FileLock> {
+    let path = &paths()?.lock_file;
+    let file = create_parent(path)
+        .and_then(|()| {
+            OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(path)
+        })
+        .map_err(|e| Error::io(path, e))?;
+    file.lock().map_err(|e| Error::io(path, e))?;
+    Ok(FileLock { _file: file })
+}
+
+/// Reads, changes and writes back `path` while holding its lock, so
+/// concurrent updates don't overwrite each other. A missing file reads as
+/// empty.
+///
+/// # Errors
+/// Returns an error if the file cannot be locked or written.
+pub fn update_lines<T>(path: &Path, update: impl FnOnce(&mut Vec<String>) -> T) -> Result<T> {
+    let _lock = lock_files()?;
+    let mut lines = read_lines(path).unwrap_or_default();
+    let result = update(&mut lines);
+    write_lines(path, &lines)?;
+    Ok(result)
+}
+
+/// [`update_lines`] for the projects file, saving the previous version to
+/// the history first when `update` changes it.
+///
+/// # Errors
+/// Returns an error if the file cannot be locked or written, or the
+/// history cannot be saved.
+pub fn update_projects<T>(
+    projects_file: &Path,
+    update: impl FnOnce(&mut Vec<String>) -> T,
+) -> Result<T> {
+    let _lock = lock_files()?;
+    let mut lines = read_lines(projects_file).unwrap_or_default();
+    let previous = lines.clone();
+    let result = update(&mut lines);
+    if lines != previous {
+        history::save(&paths()?.history_dir, &previous)?;
+        write_lines(projects_file, &lines)?;
+    }
+    Ok(result)
+}
+
+/// Splits a line of the last-used file into its path and unix time.
+fn parse_last_used_line(line: &str) -> Option<(&str, u64)> {
+    let (path, time) = line.rsplit_once('\t')?;
+    Some((path, time.parse().ok()?))
+}
+
+#[must_use]
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Moves `selected_str` to the front of the cache and records the current
+/// time for it, keeping at most [`MAX_CACHE_ENTRIES`] entries of each.
+///
+/// # Errors
+/// Returns an error if the cache or last-used file cannot be written.
+pub fn update_cache(paths: &Paths, selected_str: &str) -> Result<()> {
+    if !selected_str.is_empty() {
+        update_lines(&paths.cache_file, |cache_lines| {
+            cache_lines.retain(|line| line != selected_str);
+            cache_lines.insert(0, selected_str.to_string());
+            cache_lines.truncate(MAX_CACHE_ENTRIES);
+        })?;
+        update_lines(&paths.last_used_file, |lines| {
+            lines.retain(|line| {
+                parse_last_used_line(line).is_none_or(|(path, _)| path != selected_str)
+            });
+            lines.insert(0, format!("{selected_str}\t{}", unix_now()));
+            lines.truncate(MAX_CACHE_ENTRIES);
+        })?;
+    }
+    Ok(())
+}
+
+/// Last-used unix times, keyed by canonical path.
+///
+/// # Errors
+/// Returns an error if the home directory cannot be determined.
+pub fn get_last_used() -> Result<HashMap<String, u64>> {
+    let last_used_file = &paths()?.last_used_file;
+    let mut last_used = HashMap::new();
+    for line in read_lines(last_used_file).unwrap_or_else(|_| vec![]) {
+        if let Some((path, time)) = parse_last_used_line(&line) {
+            last_used
+                .entry(Project::new(path).canonical_path)
+                .or_insert(time);
+        }
+    }
+    Ok(last_used)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn concurrent_updates_keep_every_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lines");
+
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    for n in 0..20 {
+                        update_lines(&path, |lines| lines.push(format!("{writer}-{n}"))).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(read_lines(&path).unwrap().len(), 160);
+        let files: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, ["lines"]);
+    }
+
+    #[test]
+    fn profiles_are_detected_by_user_and_host() {
+        let dir = tempfile::tempdir().unwrap();
+        touch_file(&dir.path().join("alice")).unwrap();
+
+        assert_eq!(
+            detect_profile(dir.path(), "alice", "laptop"),
+            Some("alice".to_string())
+        );
+        touch_file(&dir.path().join("laptop")).unwrap();
+        assert_eq!(
+            detect_profile(dir.path(), "alice", "laptop"),
+            Some("laptop".to_string())
+        );
+        touch_file(&dir.path().join("alice@laptop")).unwrap();
+        assert_eq!(
+            detect_profile(dir.path(), "alice", "laptop"),
+            Some("alice@laptop".to_string())
+        );
+        assert_eq!(detect_profile(dir.path(), "bob", "server"), None);
+        assert_eq!(detect_profile(dir.path(), "", ""), None);
+    }
+}
@@ -0,0 +1,379 @@
+use crate::error::{Error, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// Runs `git` with `args` in `dir` and returns what it printed.
+///
+/// # Errors
+/// Returns an error if git is missing or the command fails.
+pub fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| Error::spawn("git", e))?;
+    if !output.status.success() {
+        return Err(Error::git(args.first().unwrap_or(&""), &output.stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Where the repositories of shorthand `owner/repo` URLs are.
+const DEFAULT_HOST: &str = "github.com";
+
+/// A repository to clone and the directory it goes in below the clone
+/// root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    /// The URL to hand to `git clone`
+    pub url: String,
+    /// The host followed by the repository's path, like
+    /// `github.com/owner/repo`
+    pub dir: PathBuf,
+}
+
+impl Remote {
+    /// Parses `https://`, `ssh://` and `file://` URLs, scp-like
+    /// `git@host:owner/repo` ones and `owner/repo` for GitHub. `file://`
+    /// URLs go below `localhost`.
+    ///
+    /// # Errors
+    /// Returns an error if the URL has no host or repository path, or looks
+    /// like an option.
+    pub fn parse(url: &str) -> Result<Self> {
+        let invalid = || Error::Config(format!("can't tell where to clone \"{url}\" to"));
+        if url.starts_with('-') {
+            return Err(invalid());
+        }
+        let (host, path, url) = if let Some((scheme, rest)) = url.split_once("://") {
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            let host = authority.rsplit('@').next().unwrap_or_default();
+            let host = host.split(':').next().unwrap_or_default();
+            let host = if host.is_empty() && scheme == "file" {
+                "localhost"
+            } else {
+                host
+            };
+            (host, path, url.to_string())
+        } else if let Some((authority, path)) = url.split_once(':') {
+            (
+                authority.rsplit('@').next().unwrap_or_default(),
+                path,
+                url.to_string(),
+            )
+        } else if Path::new(url).components().count() == 2 && !url.starts_with(['.', '/', '~']) {
+            (DEFAULT_HOST, url, format!("https://{DEFAULT_HOST}/{url}"))
+        } else {
+            return Err(invalid());
+        };
+        let path = path.trim_end_matches('/');
+        let path = Path::new(path.strip_suffix(".git").unwrap_or(path));
+        if host.is_empty()
+            || host.contains('/')
+            || path.as_os_str().is_empty()
+            || !path.components().all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(invalid());
+        }
+        Ok(Self {
+            url,
+            dir: Path::new(host).join(path),
+        })
+    }
+}
+
+/// Clones `url` into `dir`, creating the directories above it.
+///
+/// # Errors
+/// Returns an error if git is missing or the clone fails.
+pub fn clone(url: &str, dir: &Path) -> Result<()> {
+    let parent = dir.parent().unwrap_or(dir);
+    fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+    git(
+        parent,
+        &["clone", "--quiet", "--", url, &dir.to_string_lossy()],
+    )?;
+    Ok(())
+}
+
+/// A linked worktree: a checkout of another branch of a repository in a
+/// directory of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Worktree {
+    pub dir: PathBuf,
+    /// The branch checked out, or the abbreviated commit if detached
+    pub branch: String,
+}
+
+/// The linked worktrees of the repository whose main checkout is `repo`,
+/// read from `.git/worktrees` without running git.
+#[must_use]
+pub fn linked_worktrees(repo: &Path) -> Vec<Worktree> {
+    let Ok(entries) = fs::read_dir(repo.join(".git/worktrees")) else {
+        return Vec::new();
+    };
+    let mut worktrees: Vec<Worktree> = entries
+        .filter_map(|entry| {
+            let admin_dir = entry.ok()?.path();
+            // `gitdir` holds the path of the worktree's `.git` file
+            let gitdir = fs::read_to_string(admin_dir.join("gitdir")).ok()?;
+            let dir = admin_dir.join(gitdir.trim()).parent()?.to_path_buf();
+            Some(Worktree {
+                branch: head_branch(&admin_dir)?,
+                dir,
+            })
+        })
+        .filter(|worktree| worktree.dir.is_dir())
+        .collect();
+    worktrees.sort_by(|a, b| a.dir.cmp(&b.dir));
+    worktrees
+}
+
+/// The main checkout and branch of `dir` if it is a linked worktree,
+/// read from its `.git` file without running git.
+#[must_use]
+pub fn worktree_of(dir: &Path) -> Option<(PathBuf, String)> {
+    let git_file = fs::read_to_string(dir.join(".git")).ok()?;
+    let admin_dir = dir.join(git_file.trim().strip_prefix("gitdir:")?.trim());
+    // The admin directory is `<repo>/.git/worktrees/<id>`
+    let git_dir = admin_dir.parent()?.parent()?;
+    if git_dir.file_name()? != ".git" {
+        return None;
+    }
+    Some((git_dir.parent()?.to_path_buf(), head_branch(&admin_dir)?))
+}
+
+fn head_branch(git_dir: &Path) -> Option<String> {
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    Some(match head.strip_prefix("ref: refs/heads/") {
+        Some(branch) => branch.to_string(),
+        None => head.get(..7)?.to_string(),
+    })
+}
+
+/// The main checkout of the repository `dir` belongs to, even when `dir`
+/// is in a linked worktree.
+///
+/// # Errors
+/// Returns an error if `dir` is not in a git repository.
+pub fn main_worktree(dir: &Path) -> Result<PathBuf> {
+    let common_dir = git(dir, &["rev-parse", "--git-common-dir"])?;
+    let common_dir = dir.join(common_dir.trim());
+    let common_dir = fs::canonicalize(&common_dir).map_err(|e| Error::io(&common_dir, e))?;
+    Ok(match common_dir.parent() {
+        Some(parent) if common_dir.file_name().is_some_and(|name| name == ".git") => {
+            parent.to_path_buf()
+        }
+        _ => common_dir,
+    })
+}
+
+/// Checks out `branch` of `repo` into a new worktree at `dir`, creating
+/// the branch from the current commit if it doesn't exist.
+///
+/// # Errors
+/// Returns an error if `branch` looks like an option, git is missing or the
+/// worktree cannot be added.
+pub fn add_worktree(repo: &Path, dir: &Path, branch: &str) -> Result<()> {
+    if branch.is_empty() || branch.starts_with('-') {
+        return Err(Error::Config(format!("invalid branch name \"{branch}\"")));
+    }
+    let dir = dir.to_string_lossy();
+    let local_branch = format!("refs/heads/{branch}");
+    if git(repo, &["rev-parse", "--verify", "--quiet", &local_branch]).is_ok() {
+        git(repo, &["worktree", "add", "--quiet", "--", &dir, branch])?;
+    } else {
+        git(
+            repo,
+            &["worktree", "add", "--quiet", "-b", branch, "--", &dir],
+        )?;
+    }
+    Ok(())
+}
+
+/// Removes the worktree at `dir` from `repo`. Unless `force` is set, git
+/// refuses if it has changes.
+///
+/// # Errors
+/// Returns an error if git is missing or the worktree cannot be removed.
+pub fn remove_worktree(repo: &Path, dir: &Path, force: bool) -> Result<()> {
+    let dir = dir.to_string_lossy();
+    let mut args = vec!["worktree", "remove", dir.as_ref()];
+    if force {
+        args.push("--force");
+    }
+    git(repo, &args)?;
+    Ok(())
+}
+
+/// Where the checkout of a project stands, as shown next to it in the
+/// picker.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Status {
+    /// The branch checked out, or the abbreviated commit if detached
+    pub branch: String,
+    /// Whether there are changes or untracked files
+    pub dirty: bool,
+    /// Commits not yet pushed to the upstream branch
+    pub ahead: u32,
+    /// Commits on the upstream branch not yet pulled
+    pub behind: u32,
+}
+
+impl Status {
+    /// Parses the output of `git status --porcelain=v2 --branch`.
+    fn parse(porcelain: &str) -> Self {
+        let mut status = Self::default();
+        let mut oid = "";
+        for line in porcelain.lines() {
+            let Some(header) = line.strip_prefix("# ") else {
+                status.dirty = true;
+                continue;
+            };
+            match header.split_once(' ') {
+                Some(("branch.oid", value)) => oid = value,
+                Some(("branch.head", value)) => status.branch = value.to_string(),
+                Some(("branch.ab", value)) => {
+                    for count in value.split_whitespace() {
+                        if let Some(ahead) = count.strip_prefix('+') {
+                            status.ahead = ahead.parse().unwrap_or_default();
+                        } else if let Some(behind) = count.strip_prefix('-') {
+                            status.behind = behind.parse().unwrap_or_default();
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if status.branch == "(detached)" {
+            status.branch = oid.get(..7).unwrap_or(oid).to_string();
+        }
+        status
+    }
+}
+
+/// The status of the checkout `dir` is the root of, `None` if it isn't one
+/// or git is missing. Directories inside a checkout have none, so they
+/// don't show the status of the repository around them.
+#[must_use]
+pub fn status(dir: &Path) -> Option<Status> {
+    // `.git` is a file in linked worktrees
+    if !dir.join(".git").exists() {
+        return None;
+    }
+    // Optional locks would get in the way of git commands run meanwhile
+    git(
+        dir,
+        &[
+            "--no-optional-locks",
+            "status",
+            "--porcelain=v2",
+            "--branch",
+        ],
+    )
+    .ok()
+    .map(|porcelain| Status::parse(&porcelain))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_map_to_host_and_path_directories() {
+        let dir = |url: &str| Remote::parse(url).unwrap().dir;
+
+        assert_eq!(
+            dir("https://github.com/rust-lang/rust.git"),
+            Path::new("github.com/rust-lang/rust")
+        );
+        assert_eq!(
+            dir("ssh://git@gitlab.com:2222/group/sub/app/"),
+            Path::new("gitlab.com/group/sub/app")
+        );
+        assert_eq!(
+            dir("git@github.com:owner/repo.git"),
+            Path::new("github.com/owner/repo")
+        );
+        assert_eq!(
+            dir("file:///srv/git/me/tool.git"),
+            Path::new("localhost/srv/git/me/tool")
+        );
+        assert_eq!(
+            Remote::parse("owner/repo").unwrap(),
+            Remote {
+                url: "https://github.com/owner/repo".to_string(),
+                dir: PathBuf::from("github.com/owner/repo")
+            }
+        );
+        for url in [
+            "repo",
+            "./owner/repo",
+            "https://github.com/",
+            "https://host/a/../../etc",
+            "--upload-pack=touch pwned;:x/y",
+        ] {
+            assert!(matches!(Remote::parse(url), Err(Error::Config(_))), "{url}");
+        }
+    }
+
+    #[test]
+    fn worktrees_are_read_from_the_git_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("app");
+        let admin_dir = repo.join(".git/worktrees/feature");
+        let worktree = dir.path().join("app@feature");
+        fs::create_dir_all(&admin_dir).unwrap();
+        fs::create_dir_all(&worktree).unwrap();
+        fs::write(
+            admin_dir.join("gitdir"),
+            format!("{}/.git\n", worktree.display()),
+        )
+        .unwrap();
+        fs::write(admin_dir.join("HEAD"), "ref: refs/heads/feature/login\n").unwrap();
+        fs::write(
+            worktree.join(".git"),
+            format!("gitdir: {}\n", admin_dir.display()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            linked_worktrees(&repo),
+            [Worktree {
+                dir: worktree.clone(),
+                branch: "feature/login".to_string()
+            }]
+        );
+        assert_eq!(
+            worktree_of(&worktree),
+            Some((repo.clone(), "feature/login".to_string()))
+        );
+        assert_eq!(worktree_of(&repo), None);
+        fs::write(admin_dir.join("HEAD"), "0123456789abcdef\n").unwrap();
+        assert_eq!(linked_worktrees(&repo)[0].branch, "0123456");
+    }
+
+    #[test]
+    fn status_reads_branch_changes_and_upstream_counts() {
+        let status = Status::parse(
+            "# branch.oid 0123456789abcdef\n# branch.head main\n\
+             # branch.upstream origin/main\n# branch.ab +2 -1\n? notes.txt\n",
+        );
+        assert_eq!(
+            status,
+            Status {
+                branch: "main".to_string(),
+                dirty: true,
+                ahead: 2,
+                behind: 1
+            }
+        );
+
+        let status = Status::parse("# branch.oid 0123456789abcdef\n# branch.head (detached)\n");
+        assert_eq!(status.branch, "0123456");
+        assert!(!status.dirty);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::error::{Error, Result};
+use crate::files::{lock_files, read_lines, unix_now, write_lines};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where previous versions of the legacy `~/.projects` file are kept.
+pub const HISTORY_DIR: &str = ".projects_history";
+const MAX_HISTORY_ENTRIES: usize = 20;
+
+/// A saved version of the projects file, stored as `<id>-<unix time>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub id: u64,
+    pub saved_at: u64,
+    pub path: PathBuf,
+}
+
+impl Snapshot {
+    fn parse(path: PathBuf) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let (id, saved_at) = name.split_once('-')?;
+        Some(Self {
+            id: id.parse().ok()?,
+            saved_at: saved_at.parse().ok()?,
+            path,
+        })
+    }
+
+    /// The projects file as it was when this version was saved.
+    ///
+    /// # Errors
+    /// Returns an error if the snapshot cannot be read.
+    pub fn lines(&self) -> Result<Vec<String>> {
+        read_lines(&self.path).map_err(|e| Error::io(&self.path, e))
+    }
+}
+
+/// Every saved version, oldest first. A missing history has none.
+///
+/// # Errors
+/// Returns an error if the history directory cannot be read.
+pub fn snapshots(history_dir: &Path) -> Result<Vec<Snapshot>> {
+    let entries = match fs::read_dir(history_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::io(history_dir, e)),
+    };
+    let mut snapshots: Vec<Snapshot> = entries
+        .filter_map(|entry| Snapshot::parse(entry.ok()?.path()))
+        .collect();
+    snapshots.sort_by_key(|snapshot| snapshot.id);
+    Ok(snapshots)
+}
+
+/// Saves `lines` as the newest version, forgetting the oldest ones beyond
+/// [`MAX_HISTORY_ENTRIES`].
+///
+/// # Errors
+/// Returns an error if the history cannot be written.
+pub fn save(history_dir: &Path, lines: &[String]) -> Result<()> {
+    fs::create_dir_all(history_dir).map_err(|e| Error::io(history_dir, e))?;
+    let mut snapshots = snapshots(history_dir)?;
+    let id = snapshots.last().map_or(1, |snapshot| snapshot.id + 1);
+    write_lines(history_dir.join(format!("{id}-{}", unix_now())), lines)?;
+    let excess = (snapshots.len() + 1).saturating_sub(MAX_HISTORY_ENTRIES);
+    for snapshot in snapshots.drain(..excess) {
+        fs::remove_file(&snapshot.path).map_err(|e| Error::io(&snapshot.path, e))?;
+    }
+    Ok(())
+}
+
+/// Puts back version `id`, or the newest one, as `projects_file`, after
+/// saving the current version like any other change. Undoing twice thus
+/// brings back what the first undo replaced, and every version stays in
+/// the history.
+///
+/// # Errors
+/// Returns an error if there is no such version or the files cannot be
+/// written.
+pub fn restore(history_dir: &Path, projects_file: &Path, id: Option<u64>) -> Result<Snapshot> {
+    let _lock = lock_files()?;
+    let snapshots = snapshots(history_dir)?;
+    let snapshot = match id {
+        Some(id) => snapshots.iter().find(|snapshot| snapshot.id == id),
+        None => snapshots.last(),
+    }
+    .cloned()
+    .ok_or_else(|| match id {
+        Some(id) => Error::History(format!("no saved version {id}; see `tmux-leap history`")),
+        None => Error::History("no earlier version of the projects file to restore".to_string()),
+    })?;
+    // Read before saving, which may drop the oldest version
+    let lines = snapshot.lines()?;
+    save(history_dir, &read_lines(projects_file).unwrap_or_default())?;
+    write_lines(projects_file, &lines)?;
+    Ok(snapshot)
+}
+
+/// What going back from `current` to `saved` changes: the entries it
+/// brings back and the ones it removes.
+#[must_use]
+pub fn changes<'a>(saved: &'a [String], current: &'a [String]) -> (Vec<&'a str>, Vec<&'a str>) {
+    let saved_set: HashSet<&str> = saved.iter().map(String::as_str).collect();
+    let current_set: HashSet<&str> = current.iter().map(String::as_str).collect();
+    let restored = saved
+        .iter()
+        .map(String::as_str)
+        .filter(|line| !current_set.contains(line))
+        .collect();
+    let removed = current
+        .iter()
+        .map(String::as_str)
+        .filter(|line| !saved_set.contains(line))
+        .collect();
+    (restored, removed)
+}
+
+/// Formats how long ago `time` was, like `5m ago`.
+#[must_use]
+pub fn age(time: u64, now: u64) -> String {
+    let seconds = now.saturating_sub(time);
+    match seconds {
+        0..60 => format!("{seconds}s ago"),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| (*line).to_string()).collect()
+    }
+
+    #[test]
+    fn history_keeps_only_the_newest_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        for n in 0..MAX_HISTORY_ENTRIES + 2 {
+            save(dir.path(), &lines(&[&n.to_string()])).unwrap();
+        }
+
+        let snapshots = snapshots(dir.path()).unwrap();
+
+        assert_eq!(snapshots.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(snapshots[0].id, 3);
+        assert_eq!(snapshots[0].lines().unwrap(), ["2"]);
+    }
+
+    #[test]
+    fn restoring_saves_the_current_version_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = dir.path().join("history");
+        let projects_file = dir.path().join("projects");
+        save(&history, &lines(&["~/a"])).unwrap();
+        save(&history, &lines(&["~/a", "~/b"])).unwrap();
+        write_lines(&projects_file, &lines(&["~/b"])).unwrap();
+
+        let restored = restore(&history, &projects_file, Some(1)).unwrap();
+
+        assert_eq!(restored.id, 1);
+        assert_eq!(read_lines(&projects_file).unwrap(), ["~/a"]);
+        let kept: Vec<Vec<String>> = snapshots(&history)
+            .unwrap()
+            .iter()
+            .map(|s| s.lines().unwrap())
+            .collect();
+        assert_eq!(
+            kept,
+            [lines(&["~/a"]), lines(&["~/a", "~/b"]), lines(&["~/b"])]
+        );
+        restore(&history, &projects_file, None).unwrap();
+        assert_eq!(read_lines(&projects_file).unwrap(), ["~/b"]);
+        assert!(matches!(
+            restore(&dir.path().join("none"), &projects_file, None),
+            Err(Error::History(_))
+        ));
+    }
+
+    #[test]
+    fn changes_list_restored_and_removed_entries() {
+        let saved = lines(&["~/a", "~/b --depth 1"]);
+        let current = lines(&["~/b", "~/a"]);
+
+        assert_eq!(
+            changes(&saved, &current),
+            (vec!["~/b --depth 1"], vec!["~/b"])
+        );
+    }
+}
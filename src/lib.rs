@@ -0,0 +1,15 @@
+pub mod commands;
+pub mod doctor;
+pub mod error;
+pub mod files;
+pub mod git;
+pub mod history;
+pub mod multiplexer;
+pub mod output;
+pub mod picker;
+pub mod project;
+pub mod projects_file;
+pub mod scaffold;
+pub mod servers;
+pub mod template;
+pub mod tmux;
@@ -1,28 +1,145 @@
-pub mod tmux;
-
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 use clap_complete::{generate, Shell};
-use dirs::home_dir;
-use regex::Regex;
-use std::collections::HashSet;
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
-use std::{env, thread};
-use tempfile::NamedTempFile;
-extern crate whoami;
-
-const PROJECTS_FILE: &str = ".projects";
-const CACHE_FILE: &str = ".projects_cache";
-const FZF_LAYOUT: &str = "--layout=reverse --no-border --cycle --extended";
-const MAX_CACHE_ENTRIES: usize = 100;
+use std::path::{Path, PathBuf};
+use tmux_leap::commands::{
+    add_project, add_worktree, clone_and_leap, create_and_leap, delete_project, doctor,
+    edit_projects_file, execution, goto_project, list_candidates, list_projects, prune,
+    remove_worktree, resolve_entry, set_depth, show_history, show_profiles, status_projects, undo,
+    DepthSetting,
+};
+use tmux_leap::error::{Error, Result};
+use tmux_leap::files::{set_paths, Paths};
+use tmux_leap::output::OutputFormat;
+use tmux_leap::scaffold::Scaffold;
+use tmux_leap::servers::{Server, Servers};
+use tmux_leap::tmux::{Socket, Tmux};
 
 #[derive(Debug, Parser)]
-#[command(name = "tmux-leap", about = "fzf through a list of directories", version = env!("CARGO_PKG_VERSION"))]
+#[command(
+    name = "tmux-leap",
+    about = "fzf through a list of directories",
+    version = env!("CARGO_PKG_VERSION")
+)]
 struct Opt {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    /// Start the picker filtered by this query, leaping straight to the
+    /// project if it is the only match. Command names take precedence, so
+    /// any other word is taken as the query
+    query: Vec<String>,
+
+    /// Leap to the best match for the query without showing the picker
+    #[arg(long)]
+    first: bool,
+
+    /// Use the tmux server with this socket name (tmux -L). Repeat to list
+    /// sessions from several servers; the first one given is the primary
+    #[arg(
+        long,
+        short = 'L',
+        global = true,
+        env = "TMUX_LEAP_SOCKET_NAME",
+        value_delimiter = ','
+    )]
+    socket_name: Vec<String>,
+
+    /// Use the tmux server at this socket path (tmux -S). Repeatable like
+    /// --socket-name, after which it is listed
+    #[arg(
+        long,
+        short = 'S',
+        global = true,
+        env = "TMUX_LEAP_SOCKET_PATH",
+        value_delimiter = ','
+    )]
+    socket_path: Vec<PathBuf>,
+
+    /// Talk to tmux over one control-mode connection instead of a process
+    /// per command. The connection attaches like a client, so the server's
+    /// client-attached and client-detached hooks run
+    #[arg(long, global = true, env = "TMUX_LEAP_CONTROL_MODE")]
+    control_mode: bool,
+
+    /// Use this projects file instead of the default location
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Use the projects of this profile. Defaults to the profile named after
+    /// USER@HOST, HOST or USER if one exists; "default" selects no profile
+    #[arg(long, short = 'p', global = true, env = "TMUX_LEAP_PROFILE")]
+    profile: Option<String>,
+
+    /// How projects created with ctrl-o in the picker are set up
+    #[command(flatten)]
+    scaffold: ScaffoldArgs,
+}
+
+/// The options of `new`, also taken by the picker for ctrl-o.
+#[derive(Debug, Args)]
+struct ScaffoldArgs {
+    /// Create new projects in this directory
+    #[arg(
+        long,
+        env = "TMUX_LEAP_NEW_ROOT",
+        value_name = "DIR",
+        default_value = "~/projects"
+    )]
+    new_root: String,
+
+    /// Run `git init` in new projects
+    #[arg(long, env = "TMUX_LEAP_GIT_INIT")]
+    git_init: bool,
+
+    /// Copy new projects from this template in the templates directory
+    #[arg(long, env = "TMUX_LEAP_TEMPLATE", value_name = "NAME")]
+    template: Option<String>,
+}
+
+impl ScaffoldArgs {
+    /// How new projects are set up.
+    fn scaffold(&self) -> Scaffold {
+        Scaffold {
+            root: PathBuf::from(shellexpand::tilde(&self.new_root).as_ref()),
+            git_init: self.git_init,
+            template: self.template.clone(),
+        }
+    }
+}
+
+/// The options that only apply to the picker, which a command given with
+/// them would silently ignore.
+const PICKER_OPTIONS: [&str; 4] = ["first", "new_root", "git_init", "template"];
+
+impl Opt {
+    /// Every selected server, or the default one if none was selected.
+    fn servers(&self) -> Servers<Tmux> {
+        let sockets: Vec<Socket> = self
+            .socket_name
+            .iter()
+            .cloned()
+            .map(Socket::Name)
+            .chain(self.socket_path.iter().cloned().map(Socket::Path))
+            .collect();
+        let muxes = if sockets.is_empty() {
+            vec![Tmux::new()]
+        } else {
+            sockets
+                .into_iter()
+                .map(|socket| Tmux::with_socket(Some(socket)))
+                .collect()
+        };
+        Servers::new(
+            muxes
+                .into_iter()
+                .map(|mux| Server {
+                    name: mux.name(),
+                    mux: mux.with_control_mode(self.control_mode),
+                })
+                .collect(),
+        )
+    }
 }
 
 #[derive(Debug, Subcommand)]
@@ -36,19 +153,79 @@ enum Commands {
         /// Set a recursive depth for subdirectories
         #[arg(long)]
         depth: Option<u32>,
+
+        /// Keep the path as given instead of resolving its symlinks
+        #[arg(long)]
+        logical: bool,
+    },
+    /// Create a project in the new projects root and leap into it
+    #[command(name = "new")]
+    New {
+        /// The directory to create below the root, like blog or clients/acme
+        name: String,
+
+        #[command(flatten)]
+        scaffold: ScaffoldArgs,
     },
-    /// Delete a project from the .projects file
+    /// Clone a git repository into HOST/OWNER/REPO below the clone root and
+    /// leap into it
+    #[command(name = "clone")]
+    Clone {
+        /// The repository URL, or OWNER/REPO for GitHub
+        url: String,
+
+        /// Clone repositories below this directory
+        #[arg(
+            long,
+            env = "TMUX_LEAP_CLONE_ROOT",
+            value_name = "DIR",
+            default_value = "~/src"
+        )]
+        root: String,
+    },
+    /// Manage git worktrees of the repository in the current directory
+    #[command(name = "worktree", aliases = &["wt"])]
+    Worktree {
+        #[command(subcommand)]
+        command: WorktreeCommand,
+    },
+    /// Delete projects from the .projects file, chosen with fzf if no
+    /// directory is given
     #[command(name = "delete", aliases = &["del", "d"])]
-    Delete,
+    Delete {
+        /// The directories to delete, as written in the file or as any path
+        /// to them
+        dirs: Vec<String>,
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
     /// List all projects in the .projects file
     #[command(name = "list", aliases = &["ls", "l"])]
-    List,
+    List {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Plain)]
+        format: OutputFormat,
+        /// Only list projects with this --tag in the projects file
+        #[arg(long)]
+        tag: Option<String>,
+    },
     /// Display the contents of the .projects file
     #[command(name = "status", aliases = &["stat", "s"])]
-    Status,
+    Status {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Plain)]
+        format: OutputFormat,
+    },
     /// Set or remove depth for a project
     #[command(name = "set-depth", aliases = &["depth", "sd"])]
-    SetDepth,
+    SetDepth {
+        /// The directory to change, chosen with fzf if not provided
+        dir: Option<String>,
+        /// The new depth, or `none` to remove it. Read from stdin if not
+        /// provided
+        depth: Option<DepthSetting>,
+    },
     /// Edit the .projects file in your default editor
     #[command(name = "edit", aliases = &["e"])]
     Edit,
@@ -58,6 +235,49 @@ enum Commands {
         /// The project directory to go to
         dir: String,
     },
+    /// Print the ranked, deduplicated candidates the picker would show
+    #[command(name = "candidates")]
+    Candidates {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Plain)]
+        format: OutputFormat,
+    },
+    /// Print the session name and path that selecting an entry would use
+    #[command(name = "resolve")]
+    Resolve {
+        /// A picker entry or project directory
+        entry: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Plain)]
+        format: OutputFormat,
+    },
+    /// List earlier versions of the .projects file
+    #[command(name = "history")]
+    History,
+    /// Restore the .projects file to an earlier version
+    #[command(name = "undo")]
+    Undo {
+        /// The version to restore, as listed by `history`. Defaults to the newest
+        id: Option<u64>,
+    },
+    /// List the profiles, marking the active one
+    #[command(name = "profiles")]
+    Profiles,
+    /// Remove projects and cache entries whose directory no longer exists
+    #[command(name = "prune")]
+    Prune {
+        /// Only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Also kill tmux sessions whose directory no longer exists
+        #[arg(long)]
+        sessions: bool,
+    },
+    /// Check tmux, fzf and the projects file for problems
+    #[command(name = "doctor")]
+    Doctor,
     /// Generate shell completion scripts
     #[command(name = "completion", aliases = &["comp", "c"])]
     Completion {
@@ -66,110 +286,99 @@ enum Commands {
     },
 }
 
-#[derive(Debug, Clone)]
-struct Project {
-    shortened_path: String,
-    expanded_path: String,
-    tmux_display_path: String,
+#[derive(Debug, Subcommand)]
+enum WorktreeCommand {
+    /// Check out a branch into a worktree next to the repository and leap
+    /// into it, creating the branch if needed
+    #[command(name = "add")]
+    Add { branch: String },
+    /// Remove the worktree of a branch and kill its session
+    #[command(name = "remove", aliases = &["rm"])]
+    Remove {
+        branch: String,
+
+        /// Remove the worktree even if it has changes
+        #[arg(long, short)]
+        force: bool,
+    },
 }
 
-impl Project {
-    fn new(path: &str) -> Self {
-        let expanded_path = shellexpand::tilde(&path)
-            .to_string()
-            .trim_end_matches('/')
-            .to_string();
-        let shortened_path = Self::shorten_path(&expanded_path)
-            .trim_end_matches('/')
-            .to_string();
-        let tmux_display_path = Self::format_for_tmux(&shortened_path)
-            .trim_end_matches('/')
-            .to_string();
-
-        Self {
-            shortened_path,
-            expanded_path,
-            tmux_display_path,
-        }
+fn main() {
+    let matches = Opt::command().get_matches();
+    let opt = Opt::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(option) = PICKER_OPTIONS
+        .into_iter()
+        .find(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        .filter(|_| opt.command.is_some())
+    {
+        Opt::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!(
+                    "--{} only applies to the picker, not to a command",
+                    option.replace('_', "-")
+                ),
+            )
+            .exit();
     }
-
-    fn shorten_path(path_str: &str) -> String {
-        let path = PathBuf::from(path_str);
-        let home = home_dir().expect("Unable to find home directory");
-
-        if let Ok(relative) = path.strip_prefix(&home) {
-            format!("~/{}", relative.display())
+    if let Err(e) = run(opt) {
+        if matches!(e, Error::NoSelection) {
+            println!("No selection made");
         } else {
-            path.display().to_string()
+            eprintln!("tmux-leap: {e}");
         }
+        std::process::exit(e.exit_code());
     }
-
-    fn format_for_tmux(path: &str) -> String {
-        path.replace('.', "_")
-    }
-
-    fn to_fzf_display(&self) -> &str {
-        &self.shortened_path
-    }
-
-    fn exists(&self) -> bool {
-        let path = PathBuf::from(&self.expanded_path);
-        path.exists() && path.is_dir()
-    }
-
-    fn attach(&self) {
-        let tmux_session_name = &self.tmux_display_path;
-
-        let session_exists = tmux::session_exists(tmux_session_name);
-
-        if !session_exists {
-            if !tmux::create_session(tmux_session_name, &self.expanded_path) {
-                eprintln!("Failed to create new tmux session");
-                return;
-            }
-        }
-
-        if tmux::is_inside_tmux() {
-            let escaped_name = if tmux_session_name == "~" {
-                "\\~".to_string()
-            } else {
-                tmux_session_name.to_string()
-            };
-
-            if !tmux::switch_client(&escaped_name) {
-                eprintln!("Failed to switch tmux client");
-            }
-        } else if !tmux::attach_session(tmux_session_name) {
-            eprintln!("Failed to attach to tmux session");
-        }
-    }
-}
-
-trait FilterExists {
-    fn filter_exists(&self) -> Vec<Project>;
 }
 
-impl FilterExists for Vec<Project> {
-    fn filter_exists(&self) -> Vec<Project> {
-        self.iter()
-            .filter(|project| project.exists())
-            .cloned()
-            .collect()
-    }
-}
-
-fn main() {
-    let opt = Opt::parse();
+fn run(opt: Opt) -> Result<()> {
+    set_paths(Paths::resolve(opt.config.clone(), opt.profile.clone())?);
+    let servers = opt.servers();
+    let mux = &servers.primary().mux;
     match opt.command {
-        Some(Commands::Add { dir, depth }) => add_project(dir.as_deref(), depth),
-        Some(Commands::Delete) => delete_project(),
-        Some(Commands::List) => list_projects(),
-        Some(Commands::Status) => status_projects(),
-        Some(Commands::SetDepth) => set_depth(),
+        Some(Commands::Add {
+            dir,
+            depth,
+            logical,
+        }) => add_project(dir.as_deref(), depth, logical),
+        Some(Commands::New { name, scaffold }) => {
+            create_and_leap(&name, &scaffold.scaffold(), &servers)
+        }
+        Some(Commands::Clone { url, root }) => clone_and_leap(
+            &url,
+            Path::new(shellexpand::tilde(&root).as_ref()),
+            &servers,
+        ),
+        Some(Commands::Worktree { command }) => match command {
+            WorktreeCommand::Add { branch } => add_worktree(&branch, &servers),
+            WorktreeCommand::Remove { branch, force } => remove_worktree(&branch, force, mux),
+        },
+        Some(Commands::Delete { dirs, yes }) => delete_project(&dirs, yes),
+        Some(Commands::List { format, tag }) => list_projects(format, tag.as_deref(), mux),
+        Some(Commands::Status { format }) => status_projects(format, mux),
+        Some(Commands::SetDepth { dir, depth }) => set_depth(dir.as_deref(), depth),
         Some(Commands::Edit) => edit_projects_file(),
-        Some(Commands::Goto { dir }) => goto_project(&dir),
-        Some(Commands::Completion { shell }) => generate_completion(shell),
-        None => execution(),
+        Some(Commands::Goto { dir }) => goto_project(&dir, mux),
+        Some(Commands::Candidates { format }) => list_candidates(format, mux),
+        Some(Commands::Resolve { entry, format }) => resolve_entry(&entry, format, mux),
+        Some(Commands::History) => show_history(),
+        Some(Commands::Undo { id }) => undo(id),
+        Some(Commands::Profiles) => show_profiles(),
+        Some(Commands::Prune { dry_run, sessions }) => prune(dry_run, sessions, mux),
+        Some(Commands::Doctor) => doctor(mux),
+        Some(Commands::Completion { shell }) => {
+            generate_completion(shell);
+            Ok(())
+        }
+        None => {
+            let query = (!opt.query.is_empty()).then(|| opt.query.join(" "));
+            execution(
+                &servers,
+                query.as_deref(),
+                opt.first,
+                &opt.scaffold.scaffold(),
+            )
+        }
     }
 }
 
@@ -178,371 +387,3 @@ fn generate_completion(shell: Shell) {
     let bin_name = env!("CARGO_PKG_NAME");
     generate(shell, &mut cmd, bin_name, &mut std::io::stdout());
 }
-
-fn get_home_path(file: &str) -> PathBuf {
-    let mut file = file;
-    if file.starts_with('~') {
-        file = file.strip_prefix("~").unwrap();
-    }
-    home_dir()
-        .expect("Unable to find home directory")
-        .join(file)
-}
-
-fn touch_file(path: &PathBuf) {
-    OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open(path)
-        .unwrap();
-}
-
-fn read_lines<P>(filename: P) -> std::io::Result<Vec<String>>
-where
-    P: AsRef<std::path::Path>,
-{
-    let file = File::open(filename)?;
-    let buf = BufReader::new(file);
-    buf.lines().collect()
-}
-
-fn write_lines<P>(filename: P, lines: &[String]) -> std::io::Result<()>
-where
-    P: AsRef<std::path::Path>,
-{
-    let mut file = File::create(filename)?;
-    for line in lines {
-        writeln!(file, "{line}")?;
-    }
-    Ok(())
-}
-
-fn add_project(dir: Option<&str>, depth: Option<u32>) {
-    let projects_file = get_home_path(PROJECTS_FILE);
-    touch_file(&projects_file);
-    let current_dir = env::current_dir().unwrap().to_str().unwrap().to_string();
-    let dir = dir.unwrap_or(&current_dir).to_string();
-    let project = Project::new(&dir);
-    let mut lines = read_lines(&projects_file).unwrap_or_else(|_| vec![]);
-
-    // Remove any existing entry for this path
-    lines.retain(|line| {
-        let re = Regex::new(r"(.*) --depth \d+").unwrap();
-        if let Some(captures) = re.captures(line) {
-            let path = captures.get(1).unwrap().as_str();
-            path != project.shortened_path
-        } else {
-            line != &project.shortened_path
-        }
-    });
-
-    // Add the path with depth if specified
-    if let Some(depth_value) = depth {
-        lines.push(format!(
-            "{} --depth {}",
-            project.shortened_path, depth_value
-        ));
-        println!(
-            "Added \"{}\" to .projects with depth {}",
-            project.shortened_path, depth_value
-        );
-    } else {
-        lines.push(project.shortened_path.clone());
-        println!("Added \"{}\" to .projects", project.shortened_path);
-    }
-
-    write_lines(&projects_file, &lines).unwrap();
-}
-
-fn delete_project() {
-    let projects_file = get_home_path(PROJECTS_FILE);
-    let lines = read_lines(&projects_file).unwrap_or_else(|_| vec![]);
-    let mut selected = Command::new("fzf")
-        .args(FZF_LAYOUT.split_whitespace())
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("Failed to execute fzf");
-    {
-        let fzf_stdin = selected.stdin.as_mut().expect("Failed to open fzf stdin");
-        fzf_stdin
-            .write_all(lines.join("\n").as_bytes())
-            .expect("Failed to write to fzf stdin");
-    }
-    let output = selected
-        .wait_with_output()
-        .expect("Failed to read fzf output");
-    if !output.stdout.is_empty() {
-        let selected_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        let new_lines: Vec<String> = lines
-            .into_iter()
-            .filter(|line| line != &selected_str)
-            .collect();
-        write_lines(&projects_file, &new_lines).unwrap();
-        println!("Deleted \"{selected_str}\" from .projects");
-    }
-}
-
-fn get_tmux_sessions() -> Vec<Project> {
-    tmux::get_sessions()
-        .iter()
-        .map(|i| Project::new(i))
-        .collect()
-}
-
-fn get_projects() -> Vec<Project> {
-    let projects_file = get_home_path(PROJECTS_FILE);
-    let mut projects = Vec::new();
-    let mut unique_projects = HashSet::new();
-    if let Ok(lines) = read_lines(&projects_file) {
-        let re = Regex::new(r"(.*) --depth (\d+)").unwrap();
-        for line in lines {
-            if let Some(captures) = re.captures(&line) {
-                let dir = captures.get(1).unwrap().as_str();
-                let depth = captures.get(2).unwrap().as_str().parse::<u32>().unwrap();
-                projects.push(Project::new(dir));
-                let project = Project::new(dir);
-                let sub_dirs = Command::new("find")
-                    .arg("-L")
-                    .arg(&project.expanded_path)
-                    .arg("-maxdepth")
-                    .arg(depth.to_string())
-                    .arg("-type")
-                    .arg("d")
-                    .output()
-                    .expect("Failed to execute find");
-                let sub_dirs = String::from_utf8_lossy(&sub_dirs.stdout);
-                for sub_dir in sub_dirs.lines() {
-                    projects.push(Project::new(sub_dir));
-                }
-            } else {
-                projects.push(Project::new(&line));
-            }
-        }
-    }
-    projects.extend(get_tmux_sessions());
-    projects
-        .into_iter()
-        .filter(|project| unique_projects.insert(project.expanded_path.clone()))
-        .collect()
-}
-
-fn prepare_fzf_content_from_cache(cache_file: &PathBuf, temp_file: &PathBuf) -> Vec<String> {
-    let mut output_file = OpenOptions::new()
-        .append(true)
-        .open(temp_file)
-        .expect("Failed to open temp file for appending");
-    let current_session = tmux::get_current_session();
-    read_lines(cache_file)
-        .unwrap_or_else(|_| vec![])
-        .into_iter()
-        .map(|line| Project::new(&line))
-        .filter(|project| {
-            current_session
-                .as_ref()
-                .is_none_or(|session| project.tmux_display_path != *session)
-        })
-        .filter(Project::exists)
-        .scan(HashSet::new(), |seen, project| {
-            if seen.insert(project.expanded_path.clone()) {
-                writeln!(output_file, "{}", project.to_fzf_display())
-                    .expect("Failed to write to temp file");
-                Some(project.shortened_path.clone())
-            } else {
-                None
-            }
-        })
-        .collect()
-}
-
-fn execution() {
-    let cache_file = get_home_path(CACHE_FILE);
-    touch_file(&cache_file);
-    let temp_file = NamedTempFile::new().expect("Failed to create temporary file");
-    let temp_path = temp_file.path().to_path_buf();
-    let cache_lines = prepare_fzf_content_from_cache(&cache_file, &temp_path);
-    let fzf_process = start_fzf(&temp_path);
-    let mut seen_items: HashSet<String> = cache_lines.into_iter().collect();
-    let temp_path_clone = temp_path;
-    thread::spawn(move || {
-        let projects = load_and_filter_projects();
-        let additional_fzf_through = prepare_fzf_content(&projects);
-        let mut file = OpenOptions::new()
-            .append(true)
-            .open(&temp_path_clone)
-            .expect("Failed to open temp file for appending");
-        for item in additional_fzf_through {
-            if seen_items.insert(item.clone()) {
-                writeln!(file, "{item}").expect("Failed to write to temp file");
-            }
-        }
-    });
-    let selected_str = wait_for_fzf_selection(fzf_process);
-    {
-        let cleanup_result = cleanup(&cache_file, &selected_str);
-        if let Err(e) = cleanup_result {
-            eprintln!("Cleanup failed: {e}");
-        }
-    }
-    if selected_str.is_empty() {
-        println!("No selection made");
-        return;
-    }
-    let selected_project = Project::new(&selected_str);
-    load_and_filter_projects()
-        .iter()
-        .find(|p| p.expanded_path == selected_project.expanded_path)
-        .map(|p| p.attach())
-        .expect("Selected project not found");
-}
-
-fn cleanup(cache_file: &PathBuf, selected_str: &str) -> std::io::Result<()> {
-    if !selected_str.is_empty() {
-        let mut cache_lines = read_lines(cache_file).unwrap_or_else(|_| vec![]);
-        cache_lines.retain(|line| line != selected_str);
-        cache_lines.insert(0, selected_str.to_string());
-        if cache_lines.len() > MAX_CACHE_ENTRIES {
-            cache_lines.truncate(MAX_CACHE_ENTRIES);
-        }
-        write_lines(cache_file, &cache_lines)?;
-    }
-    Ok(())
-}
-
-fn start_fzf(temp_file: &PathBuf) -> std::process::Child {
-    Command::new("sh")
-        .arg("-c")
-        .arg(format!(
-            "tail -f -n +0 {} | fzf {}",
-            temp_file.display(),
-            FZF_LAYOUT
-        ))
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("Failed to execute fzf")
-}
-
-fn load_and_filter_projects() -> Vec<Project> {
-    get_projects().filter_exists()
-}
-
-fn prepare_fzf_content(projects: &[Project]) -> Vec<String> {
-    let mut fzf_through: Vec<String> = Vec::new();
-    let mut seen = HashSet::new();
-    for project in projects {
-        let display = project.to_fzf_display();
-        if seen.insert(project.expanded_path.clone()) {
-            fzf_through.push(display.to_string());
-        }
-    }
-    fzf_through
-}
-
-fn wait_for_fzf_selection(fzf_process: std::process::Child) -> String {
-    let output = fzf_process
-        .wait_with_output()
-        .expect("Failed to read fzf output");
-    String::from_utf8_lossy(&output.stdout).trim().to_string()
-}
-
-fn list_projects() {
-    let projects = get_projects();
-    for project in projects {
-        println!("{}", project.shortened_path);
-    }
-}
-
-fn status_projects() {
-    let projects_file = get_home_path(PROJECTS_FILE);
-    let lines = read_lines(&projects_file).unwrap_or_else(|_| vec![]);
-    for line in lines {
-        println!("{line}");
-    }
-}
-
-fn set_depth() {
-    let projects_file = get_home_path(PROJECTS_FILE);
-    let lines = read_lines(&projects_file).unwrap_or_else(|_| vec![]);
-    let mut selected = Command::new("fzf")
-        .args(FZF_LAYOUT.split_whitespace())
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("Failed to execute fzf");
-    {
-        let fzf_stdin = selected.stdin.as_mut().expect("Failed to open fzf stdin");
-        fzf_stdin
-            .write_all(lines.join("\n").as_bytes())
-            .expect("Failed to write to fzf stdin");
-    }
-    let output = selected
-        .wait_with_output()
-        .expect("Failed to read fzf output");
-    if !output.stdout.is_empty() {
-        let selected_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        println!("Set depth for {selected_str}: (Press Enter to remove depth, Ctrl+C to cancel)");
-        let mut depth_input = String::new();
-        std::io::stdin()
-            .read_line(&mut depth_input)
-            .expect("Failed to read depth input");
-        let depth_input = depth_input.trim();
-        let re = Regex::new(r"(.*) --depth \d+").unwrap();
-        let mut new_lines: Vec<String> = lines
-            .into_iter()
-            .filter(|line| !re.is_match(line) || !line.starts_with(&selected_str))
-            .collect();
-        if depth_input.is_empty() {
-            new_lines.push(selected_str.clone());
-        } else {
-            new_lines.push(format!("{selected_str} --depth {depth_input}"));
-        }
-        new_lines.sort();
-        write_lines(&projects_file, &new_lines).unwrap();
-        println!("Set depth for \"{selected_str}\" to {depth_input}");
-    }
-}
-
-fn edit_projects_file() {
-    let projects_file = get_home_path(PROJECTS_FILE);
-    touch_file(&projects_file);
-    let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
-    let status = Command::new(editor)
-        .arg(projects_file)
-        .status()
-        .expect("Failed to launch editor");
-    if !status.success() {
-        eprintln!("Editor exited with non-zero status: {}", status);
-    }
-}
-
-fn goto_project(dir: &str) {
-    let project = Project::new(dir);
-
-    // Check if the directory exists
-    if !project.exists() {
-        eprintln!(
-            "Directory \"{}\" does not exist or is not a directory",
-            project.expanded_path
-        );
-        return;
-    }
-
-    if tmux::is_inside_tmux() {
-        // Inside tmux: just switch/attach normally
-        project.attach();
-    } else {
-        // Outside tmux: ensure session exists, then attach using exec to persist
-        let tmux_session_name = &project.tmux_display_path;
-
-        if !tmux::session_exists(tmux_session_name) {
-            if !tmux::create_session(tmux_session_name, &project.expanded_path) {
-                eprintln!("Failed to create new tmux session");
-                return;
-            }
-        }
-
-        // Replace current process with tmux attach (this persists)
-        tmux::attach_session_exec(tmux_session_name, &project.expanded_path);
-    }
-}
@@ -0,0 +1,369 @@
+use crate::error::Result;
+
+/// A session along with the number of clients attached to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub name: String,
+    pub attached: u32,
+    pub path: String,
+}
+
+/// The session operations tmux-leap needs from a terminal multiplexer.
+///
+/// [`crate::tmux::Tmux`] drives the real tmux CLI, [`fake::FakeMultiplexer`]
+/// keeps everything in memory for tests.
+pub trait Multiplexer {
+    /// Whether tmux-leap is running inside a client of this multiplexer.
+    fn is_inside(&self) -> bool;
+
+    /// Gets every session. A server that isn't running has no sessions.
+    ///
+    /// # Errors
+    /// Returns an error if the multiplexer cannot be queried.
+    fn sessions(&self) -> Result<Vec<SessionInfo>>;
+
+    /// Checks if a session with the given name exists.
+    ///
+    /// # Errors
+    /// Returns an error if the multiplexer cannot be queried.
+    fn session_exists(&self, session_name: &str) -> Result<bool> {
+        Ok(self
+            .sessions()?
+            .iter()
+            .any(|session| session.name == session_name))
+    }
+
+    /// Creates a detached session with the given name in `dir`.
+    ///
+    /// # Errors
+    /// Returns an error if the session cannot be created.
+    fn create_session(&self, session_name: &str, dir: &str) -> Result<()>;
+
+    /// Names the first window of a session and returns its id.
+    ///
+    /// # Errors
+    /// Returns an error if the session doesn't exist.
+    fn name_first_window(&self, session_name: &str, window_name: &str) -> Result<String>;
+
+    /// Opens a window named `window_name` in `dir` after the other windows
+    /// of a session, without switching to it, and returns its id.
+    ///
+    /// # Errors
+    /// Returns an error if the window cannot be created.
+    fn new_window(&self, session_name: &str, window_name: &str, dir: &str) -> Result<String>;
+
+    /// Types `command` into the shell of a window and runs it.
+    ///
+    /// # Errors
+    /// Returns an error if the window doesn't exist.
+    fn send_command(&self, window_id: &str, command: &str) -> Result<()>;
+
+    /// Kills the session with exactly this name.
+    ///
+    /// # Errors
+    /// Returns an error if the session cannot be killed.
+    fn kill_session(&self, session_name: &str) -> Result<()>;
+
+    /// Switches the current client to the specified session.
+    ///
+    /// # Errors
+    /// Returns an error if the client cannot be switched.
+    fn switch_client(&self, session_name: &str) -> Result<()>;
+
+    /// Attaches to the specified session, returning once the client detaches.
+    ///
+    /// # Errors
+    /// Returns an error if the session cannot be attached.
+    fn attach_session(&self, session_name: &str) -> Result<()>;
+
+    /// Attaches to the specified session in place of the current process,
+    /// so the client persists after tmux-leap is gone.
+    ///
+    /// # Errors
+    /// Returns an error if the session cannot be attached. Implementations
+    /// that replace the process only ever return on failure.
+    fn attach_session_exec(&self, session_name: &str, dir: &str) -> Result<()>;
+
+    /// Gets the name of the current session, or `None` outside a client.
+    ///
+    /// # Errors
+    /// Returns an error if the multiplexer cannot be queried.
+    fn current_session(&self) -> Result<Option<String>>;
+
+    /// A shell command that attaches a new client to `session_name` on this
+    /// server, for handing over to another server's client.
+    fn attach_command(&self, session_name: &str) -> String;
+
+    /// Detaches the current client and runs `shell_command` in its place,
+    /// used to move a client from one server to another.
+    ///
+    /// # Errors
+    /// Returns an error if there is no client to detach.
+    fn detach_client_exec(&self, shell_command: &str) -> Result<()>;
+}
+
+pub mod fake {
+    use super::{Multiplexer, SessionInfo};
+    use crate::error::{Error, Result};
+    use std::sync::{Arc, Mutex, MutexGuard};
+
+    #[derive(Debug, Default)]
+    struct State {
+        sessions: Vec<SessionInfo>,
+        windows: Vec<Window>,
+        current: Option<String>,
+        detached_with: Option<String>,
+    }
+
+    /// A window of a fake session along with the commands run in it.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Window {
+        pub id: String,
+        pub session: String,
+        pub name: String,
+        pub commands: Vec<String>,
+    }
+
+    /// An in-memory multiplexer for exercising session logic without tmux.
+    /// Clones share the same server state.
+    #[derive(Debug, Clone, Default)]
+    pub struct FakeMultiplexer {
+        inside: bool,
+        state: Arc<Mutex<State>>,
+    }
+
+    impl FakeMultiplexer {
+        /// A fake server with no sessions, used from outside any client.
+        #[must_use]
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Adds an existing session rooted at `path`.
+        #[must_use]
+        pub fn with_session(self, name: &str, path: &str) -> Self {
+            self.state().sessions.push(SessionInfo {
+                name: name.to_string(),
+                attached: 0,
+                path: path.to_string(),
+            });
+            self
+        }
+
+        /// Pretends to run inside a client attached to session `name`.
+        #[must_use]
+        pub fn inside(self, name: &str) -> Self {
+            let fake = self.with_session(name, "/");
+            {
+                let mut state = fake.state();
+                state.current = Some(name.to_string());
+                if let Some(session) = state.sessions.iter_mut().find(|s| s.name == name) {
+                    session.attached += 1;
+                }
+            }
+            Self {
+                inside: true,
+                ..fake
+            }
+        }
+
+        /// The session the client is currently on, if any.
+        #[must_use]
+        pub fn current(&self) -> Option<String> {
+            self.state().current.clone()
+        }
+
+        /// The command the client was replaced with by
+        /// [`Multiplexer::detach_client_exec`], if it was detached.
+        #[must_use]
+        pub fn detached_with(&self) -> Option<String> {
+            self.state().detached_with.clone()
+        }
+
+        /// Every session, as `tmux list-sessions` would report them.
+        #[must_use]
+        pub fn session_list(&self) -> Vec<SessionInfo> {
+            self.state().sessions.clone()
+        }
+
+        /// The windows of session `name`, in order.
+        #[must_use]
+        pub fn windows(&self, name: &str) -> Vec<Window> {
+            self.state()
+                .windows
+                .iter()
+                .filter(|window| window.session == name)
+                .cloned()
+                .collect()
+        }
+
+        fn state(&self) -> MutexGuard<'_, State> {
+            self.state
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+        }
+
+        fn attach(&self, action: &str, session_name: &str) -> Result<()> {
+            let mut state = self.state();
+            let session_name = unescape(session_name);
+            let Some(session) = state.sessions.iter_mut().find(|s| s.name == session_name) else {
+                return Err(Error::Tmux {
+                    action: action.to_string(),
+                    message: format!("can't find session: {session_name}"),
+                });
+            };
+            session.attached += 1;
+            state.current = Some(session_name.to_string());
+            Ok(())
+        }
+    }
+
+    /// Adds a window to a session, numbering ids like tmux's `@0`, `@1`.
+    fn push_window(state: &mut State, session_name: &str, window_name: &str) -> String {
+        let id = format!("@{}", state.windows.len());
+        state.windows.push(Window {
+            id: id.clone(),
+            session: session_name.to_string(),
+            name: window_name.to_string(),
+            commands: Vec::new(),
+        });
+        id
+    }
+
+    /// Undoes the `\~` escaping tmux needs for a session named `~`.
+    fn unescape(session_name: &str) -> &str {
+        if session_name == r"\~" {
+            "~"
+        } else {
+            session_name
+        }
+    }
+
+    impl Multiplexer for FakeMultiplexer {
+        fn is_inside(&self) -> bool {
+            self.inside
+        }
+
+        fn sessions(&self) -> Result<Vec<SessionInfo>> {
+            Ok(self.session_list())
+        }
+
+        fn create_session(&self, session_name: &str, dir: &str) -> Result<()> {
+            let mut state = self.state();
+            if state.sessions.iter().any(|s| s.name == session_name) {
+                return Err(Error::Tmux {
+                    action: "new-session".to_string(),
+                    message: format!("duplicate session: {session_name}"),
+                });
+            }
+            state.sessions.push(SessionInfo {
+                name: session_name.to_string(),
+                attached: 0,
+                path: dir.to_string(),
+            });
+            push_window(&mut state, session_name, "");
+            Ok(())
+        }
+
+        fn name_first_window(&self, session_name: &str, window_name: &str) -> Result<String> {
+            let mut state = self.state();
+            let Some(window) = state.windows.iter_mut().find(|w| w.session == session_name) else {
+                return Err(Error::Tmux {
+                    action: "rename-window".to_string(),
+                    message: format!("can't find session: {session_name}"),
+                });
+            };
+            window.name = window_name.to_string();
+            Ok(window.id.clone())
+        }
+
+        fn new_window(&self, session_name: &str, window_name: &str, _dir: &str) -> Result<String> {
+            let mut state = self.state();
+            if !state.sessions.iter().any(|s| s.name == session_name) {
+                return Err(Error::Tmux {
+                    action: "new-window".to_string(),
+                    message: format!("can't find session: {session_name}"),
+                });
+            }
+            Ok(push_window(&mut state, session_name, window_name))
+        }
+
+        fn send_command(&self, window_id: &str, command: &str) -> Result<()> {
+            let mut state = self.state();
+            let Some(window) = state.windows.iter_mut().find(|w| w.id == window_id) else {
+                return Err(Error::Tmux {
+                    action: "send-keys".to_string(),
+                    message: format!("can't find window: {window_id}"),
+                });
+            };
+            window.commands.push(command.to_string());
+            Ok(())
+        }
+
+        fn kill_session(&self, session_name: &str) -> Result<()> {
+            let mut state = self.state();
+            let before = state.sessions.len();
+            state.sessions.retain(|s| s.name != session_name);
+            state.windows.retain(|w| w.session != session_name);
+            if state.sessions.len() == before {
+                return Err(Error::Tmux {
+                    action: "kill-session".to_string(),
+                    message: format!("can't find session: {session_name}"),
+                });
+            }
+            if state.current.as_deref() == Some(session_name) {
+                state.current = None;
+            }
+            Ok(())
+        }
+
+        fn switch_client(&self, session_name: &str) -> Result<()> {
+            if !self.inside {
+                return Err(Error::Tmux {
+                    action: "switch-client".to_string(),
+                    message: "no current client".to_string(),
+                });
+            }
+            let previous = self.current();
+            self.attach("switch-client", session_name)?;
+            if let Some(previous) = previous {
+                let mut state = self.state();
+                if let Some(session) = state.sessions.iter_mut().find(|s| s.name == previous) {
+                    session.attached -= 1;
+                }
+            }
+            Ok(())
+        }
+
+        fn attach_session(&self, session_name: &str) -> Result<()> {
+            self.attach("attach-session", session_name)
+        }
+
+        fn attach_session_exec(&self, session_name: &str, _dir: &str) -> Result<()> {
+            self.attach("attach-session", session_name)
+        }
+
+        fn current_session(&self) -> Result<Option<String>> {
+            Ok(if self.inside { self.current() } else { None })
+        }
+
+        fn attach_command(&self, session_name: &str) -> String {
+            format!("fake attach-session -t {session_name}")
+        }
+
+        fn detach_client_exec(&self, shell_command: &str) -> Result<()> {
+            let mut state = self.state();
+            let Some(previous) = state.current.take().filter(|_| self.inside) else {
+                return Err(Error::Tmux {
+                    action: "detach-client".to_string(),
+                    message: "no current client".to_string(),
+                });
+            };
+            if let Some(session) = state.sessions.iter_mut().find(|s| s.name == previous) {
+                session.attached -= 1;
+            }
+            state.detached_with = Some(shell_command.to_string());
+            Ok(())
+        }
+    }
+}
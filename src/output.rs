@@ -0,0 +1,131 @@
+use crate::error::{Error, Result};
+use crate::files::get_last_used;
+use crate::multiplexer::Multiplexer;
+use crate::project::{DepthSource, Project};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One display path per line
+    Plain,
+    /// A JSON array of project records
+    Json,
+    /// Tab-separated records: expanded path, display path, session name,
+    /// session exists, attached clients, depth source, last used, tags,
+    /// worktree branch
+    Tsv,
+    /// NUL-terminated expanded paths, for `xargs -0`
+    Null,
+}
+
+/// Machine-readable description of a project, used by `--format`.
+#[derive(Debug, Serialize)]
+pub struct ProjectRecord {
+    pub expanded_path: String,
+    pub display_path: String,
+    pub session_name: String,
+    pub session_exists: bool,
+    pub attached_clients: u32,
+    pub depth_source: Option<DepthSource>,
+    pub last_used: Option<u64>,
+    pub tags: Vec<String>,
+    pub branch: Option<String>,
+}
+
+/// Describes each project together with its session and cache state.
+///
+/// # Errors
+/// Returns an error if the sessions cannot be listed.
+pub fn build_records(projects: &[Project], mux: &dyn Multiplexer) -> Result<Vec<ProjectRecord>> {
+    let sessions: HashMap<String, u32> = mux
+        .sessions()?
+        .into_iter()
+        .map(|session| (session.name, session.attached))
+        .collect();
+    let last_used = get_last_used()?;
+    Ok(projects
+        .iter()
+        .map(|project| {
+            let attached = sessions.get(&project.tmux_display_path);
+            ProjectRecord {
+                expanded_path: project.expanded_path.clone(),
+                display_path: project.shortened_path.clone(),
+                session_name: project.tmux_display_path.clone(),
+                session_exists: attached.is_some(),
+                attached_clients: attached.copied().unwrap_or(0),
+                depth_source: project.depth_source.clone(),
+                last_used: last_used.get(&project.canonical_path).copied(),
+                tags: project.tags.clone(),
+                branch: project.branch.clone(),
+            }
+        })
+        .collect())
+}
+
+/// Prints `records` to stdout in `format`. A reader that stops early, like
+/// `head`, isn't an error.
+///
+/// # Errors
+/// Returns an error if stdout cannot be written.
+pub fn print_records(records: &[ProjectRecord], format: OutputFormat) -> Result<()> {
+    match write_records(&mut std::io::stdout().lock(), records, format) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.map_err(|e| Error::io("<stdout>", e)),
+    }
+}
+
+fn write_records(
+    out: &mut impl Write,
+    records: &[ProjectRecord],
+    format: OutputFormat,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Plain => {
+            for record in records {
+                writeln!(out, "{}", record.display_path)?;
+            }
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, records)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Tsv => {
+            for record in records {
+                let depth_source = record
+                    .depth_source
+                    .as_ref()
+                    .map_or_else(|| "-".to_string(), |s| format!("{}:{}", s.root, s.depth));
+                let last_used = record
+                    .last_used
+                    .map_or_else(|| "-".to_string(), |t| t.to_string());
+                let tags = if record.tags.is_empty() {
+                    "-".to_string()
+                } else {
+                    record.tags.join(",")
+                };
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    record.expanded_path,
+                    record.display_path,
+                    record.session_name,
+                    record.session_exists,
+                    record.attached_clients,
+                    depth_source,
+                    last_used,
+                    tags,
+                    record.branch.as_deref().unwrap_or("-")
+                )?;
+            }
+        }
+        OutputFormat::Null => {
+            for record in records {
+                write!(out, "{}\0", record.expanded_path)?;
+            }
+        }
+    }
+    out.flush()
+}
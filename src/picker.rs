@@ -0,0 +1,252 @@
+use crate::error::{Error, Result};
+use crate::files::write_lines;
+use crate::git::{self, Status};
+use crate::multiplexer::Multiplexer;
+use crate::project::Project;
+use crate::servers::Servers;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+const FZF_LAYOUT: &str = "--layout=reverse --no-border --cycle --extended";
+
+/// Lets the user pick one of `lines` with fzf.
+///
+/// # Errors
+/// Returns [`Error::NoSelection`] if the user aborts without choosing.
+pub fn select_with_fzf(lines: &[String]) -> Result<String> {
+    selected(&run_fzf(lines, &[])?)
+}
+
+/// Lets the user pick one of `lines` with fzf, starting from `query`. A
+/// single match is chosen without showing the picker.
+///
+/// # Errors
+/// Returns [`Error::NoSelection`] if nothing matches or the user aborts.
+pub fn select_with_query(lines: &[String], query: &str) -> Result<String> {
+    selected(&run_fzf(
+        lines,
+        &["--query", query, "--select-1", "--exit-0"],
+    )?)
+}
+
+/// The line of `lines` fzf ranks first for `query`, without showing the
+/// picker.
+///
+/// # Errors
+/// Returns [`Error::NoSelection`] if nothing matches.
+pub fn best_match(lines: &[String], query: &str) -> Result<String> {
+    selected(
+        run_fzf(lines, &["--filter", query])?
+            .lines()
+            .next()
+            .unwrap_or_default(),
+    )
+}
+
+fn run_fzf(lines: &[String], options: &[&str]) -> Result<String> {
+    let mut fzf = Command::new("fzf")
+        .args(FZF_LAYOUT.split_whitespace())
+        .args(options)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::spawn("fzf", e))?;
+    if let Some(mut fzf_stdin) = fzf.stdin.take() {
+        // fzf may exit before reading everything, so a broken pipe is fine here
+        let _ = fzf_stdin.write_all(lines.join("\n").as_bytes());
+    }
+    let output = fzf.wait_with_output().map_err(|e| Error::spawn("fzf", e))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn selected(output: &str) -> Result<String> {
+    let selected_str = output.trim().to_string();
+    if selected_str.is_empty() {
+        Err(Error::NoSelection)
+    } else {
+        Ok(selected_str)
+    }
+}
+
+/// The key that creates a project named after the query in the main picker.
+pub const CREATE_KEY: &str = "ctrl-o";
+
+/// What the user did in the main picker.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PickerChoice {
+    /// The query as typed
+    pub query: String,
+    /// Whether [`CREATE_KEY`] was pressed rather than enter
+    pub create: bool,
+    /// The chosen entry, empty if nothing was chosen
+    pub selected: String,
+}
+
+/// Starts fzf on the entries written to its stdin, each ending in a tab
+/// and a git status column that is shown but left out of matching. The
+/// column starts out empty; once stdin is closed fzf waits for
+/// `statuses_file`, written by [`write_statuses`], and reloads the entries
+/// from it.
+///
+/// # Errors
+/// Returns an error if the shell cannot be started.
+pub fn start_fzf(statuses_file: &Path) -> Result<Child> {
+    let statuses_file = statuses_file.display();
+    Command::new("sh")
+        .arg("-c")
+        .arg(format!(
+            "fzf {FZF_LAYOUT} --ansi --delimiter='\t' --nth=1 \
+             --bind=\"load:unbind(load)+reload-sync(until [ -e '{statuses_file}' ]; \
+             do sleep 0.1; done; cat '{statuses_file}')\" \
+             --print-query --expect={CREATE_KEY} \
+             --header='{CREATE_KEY}: create a project named after the query'"
+        ))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::spawn("sh", e))
+}
+
+/// Waits for the picker started by [`start_fzf`] and returns what the user
+/// chose.
+///
+/// # Errors
+/// Returns an error if fzf is missing or its output cannot be read.
+pub fn wait_for_fzf_selection(fzf_process: Child) -> Result<PickerChoice> {
+    let output = fzf_process
+        .wait_with_output()
+        .map_err(|e| Error::spawn("fzf", e))?;
+    // The shell reports 127 when fzf itself could not be found
+    if output.status.code() == Some(127) {
+        return Err(Error::DependencyMissing {
+            program: "fzf".to_string(),
+        });
+    }
+    // --print-query and --expect put the query and the key before the entry
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    Ok(PickerChoice {
+        query: lines.next().unwrap_or_default().to_string(),
+        create: lines.next() == Some(CREATE_KEY),
+        selected: lines
+            .next()
+            .map(|line| line.rsplit_once('\t').map_or(line, |(entry, _)| entry))
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    })
+}
+
+/// How many repositories have their status read at once.
+const STATUS_WORKERS: usize = 8;
+
+const BRANCH_COLOR: &str = "\x1b[35m";
+const DIRTY_COLOR: &str = "\x1b[31m";
+const AHEAD_COLOR: &str = "\x1b[32m";
+const BEHIND_COLOR: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// The picker entries of a project.
+#[derive(Debug, Clone)]
+pub struct Shown {
+    pub project: Project,
+    pub entries: Vec<String>,
+}
+
+/// Writes the picker entries of `projects` that aren't in `seen` to the
+/// picker started by [`start_fzf`], with an empty status column, and
+/// returns them.
+///
+/// # Errors
+/// Returns an error if a server cannot be queried or the picker has gone
+/// away.
+pub fn write_entries<M: Multiplexer>(
+    fzf_stdin: &mut impl Write,
+    projects: &[Project],
+    servers: &Servers<M>,
+    seen: &mut HashSet<String>,
+) -> Result<Vec<Shown>> {
+    let mut shown = Vec::new();
+    for (project, entries) in projects.iter().zip(servers.entries_by_project(projects)?) {
+        let entries: Vec<String> = entries
+            .into_iter()
+            .filter(|entry| seen.insert(entry.clone()))
+            .collect();
+        for entry in &entries {
+            writeln!(fzf_stdin, "{entry}\t").map_err(|e| Error::spawn("fzf", e))?;
+        }
+        if !entries.is_empty() {
+            shown.push(Shown {
+                project: project.clone(),
+                entries,
+            });
+        }
+    }
+    fzf_stdin.flush().map_err(|e| Error::spawn("fzf", e))?;
+    Ok(shown)
+}
+
+/// Reads the git status of every project in `shown`, several at a time,
+/// and writes their entries with the status column filled in to
+/// `statuses_file` for the picker to reload. The file only appears once it
+/// is complete.
+///
+/// # Errors
+/// Returns an error if the file cannot be written.
+pub fn write_statuses(shown: &[Shown], statuses_file: &Path) -> Result<()> {
+    let next = AtomicUsize::new(0);
+    let statuses = Mutex::new(vec![None; shown.len()]);
+    thread::scope(|scope| {
+        for _ in 0..STATUS_WORKERS.min(shown.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(shown) = shown.get(index) else {
+                    return;
+                };
+                let status = git::status(Path::new(&shown.project.expanded_path));
+                if let Ok(mut statuses) = statuses.lock() {
+                    statuses[index] = status;
+                }
+            });
+        }
+    });
+    let statuses = statuses.into_inner().unwrap_or_default();
+    let mut lines = Vec::new();
+    for (shown, status) in shown.iter().zip(&statuses) {
+        let column = status_column(status.as_ref());
+        lines.extend(
+            shown
+                .entries
+                .iter()
+                .map(|entry| format!("{entry}\t{column}")),
+        );
+    }
+    // Written to a temporary file and moved into place, so the picker
+    // never reloads a partial one
+    write_lines(statuses_file, &lines)
+}
+
+/// The branch, a `*` if there are changes and the commits ahead of and
+/// behind the upstream branch, coloured for fzf's `--ansi`.
+fn status_column(status: Option<&Status>) -> String {
+    let Some(status) = status else {
+        return String::new();
+    };
+    let mut column = format!("{BRANCH_COLOR}{}{RESET}", status.branch);
+    if status.dirty {
+        let _ = write!(column, " {DIRTY_COLOR}*{RESET}");
+    }
+    if status.ahead > 0 {
+        let _ = write!(column, " {AHEAD_COLOR}↑{}{RESET}", status.ahead);
+    }
+    if status.behind > 0 {
+        let _ = write!(column, " {BEHIND_COLOR}↓{}{RESET}", status.behind);
+    }
+    column
+}
@@ -0,0 +1,568 @@
+use crate::error::{Error, Result};
+use crate::files::{paths, read_lines};
+use crate::git::{linked_worktrees, worktree_of};
+use crate::multiplexer::Multiplexer;
+use crate::projects_file::{read_entries, Entry, ProjectEntry};
+use dirs::home_dir;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// The `--depth` entry a project was discovered through.
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthSource {
+    pub root: String,
+    pub depth: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub shortened_path: String,
+    pub expanded_path: String,
+    /// `expanded_path` with symlinks resolved, to tell whether two projects
+    /// are the same directory
+    pub canonical_path: String,
+    pub tmux_display_path: String,
+    pub depth_source: Option<DepthSource>,
+    pub tags: Vec<String>,
+    /// The branch checked out, for a linked git worktree
+    pub branch: Option<String>,
+}
+
+impl Project {
+    #[must_use]
+    pub fn new(path: &str) -> Self {
+        let expanded_path = normalize(Path::new(shellexpand::tilde(&path).as_ref()))
+            .to_string_lossy()
+            .trim_end_matches('/')
+            .to_string();
+        let canonical_path = Self::canonicalize(&expanded_path);
+        let shortened_path = Self::shorten_path(&expanded_path)
+            .trim_end_matches('/')
+            .to_string();
+        let mut tmux_display_path = Self::format_for_tmux(&shortened_path)
+            .trim_end_matches('/')
+            .to_string();
+        // A linked worktree's session is named after its repository and
+        // branch, like `~/src/app@feature`
+        let worktree = Path::new(&expanded_path)
+            .is_absolute()
+            .then(|| worktree_of(Path::new(&expanded_path)))
+            .flatten();
+        let branch = worktree.map(|(repo, branch)| {
+            let repo = Self::shorten_path(&repo.to_string_lossy());
+            tmux_display_path = Self::format_for_tmux(&format!("{repo}@{branch}"));
+            branch
+        });
+
+        Self {
+            shortened_path,
+            expanded_path,
+            canonical_path,
+            tmux_display_path,
+            depth_source: None,
+            tags: Vec::new(),
+            branch,
+        }
+    }
+
+    /// The linked worktrees of the repository checked out here, with the
+    /// project's tags.
+    #[must_use]
+    pub fn linked_worktrees(&self) -> Vec<Self> {
+        linked_worktrees(Path::new(&self.expanded_path))
+            .into_iter()
+            .map(|worktree| Self {
+                tags: self.tags.clone(),
+                ..Self::new(&worktree.dir.to_string_lossy())
+            })
+            .collect()
+    }
+
+    #[must_use]
+    pub fn with_depth_source(mut self, root: &str, depth: u32) -> Self {
+        self.depth_source = Some(DepthSource {
+            root: root.to_string(),
+            depth,
+        });
+        self
+    }
+
+    /// Applies the `--name` and `--tag` options of the entry the project
+    /// comes from.
+    #[must_use]
+    pub fn with_options(mut self, entry: &ProjectEntry) -> Self {
+        if let Some(name) = &entry.name {
+            self.tmux_display_path = Self::format_for_tmux(name);
+        }
+        self.tags.clone_from(&entry.tags);
+        self
+    }
+
+    fn shorten_path(path_str: &str) -> String {
+        let path = PathBuf::from(path_str);
+        let homes = home_dir()
+            .into_iter()
+            .flat_map(|home| [fs::canonicalize(&home).ok(), Some(home)])
+            .flatten();
+
+        for home in homes {
+            if let Ok(relative) = path.strip_prefix(&home) {
+                return format!("~/{}", relative.display());
+            }
+        }
+        path.display().to_string()
+    }
+
+    /// Resolves the symlinks in an absolute path. Session names and paths
+    /// that don't exist are kept as they are.
+    fn canonicalize(expanded_path: &str) -> String {
+        if !Path::new(expanded_path).is_absolute() {
+            return expanded_path.to_string();
+        }
+        fs::canonicalize(expanded_path).map_or_else(
+            |_| expanded_path.to_string(),
+            |path| path.to_string_lossy().into_owned(),
+        )
+    }
+
+    fn format_for_tmux(path: &str) -> String {
+        path.replace('.', "_")
+    }
+
+    #[must_use]
+    pub fn to_fzf_display(&self) -> String {
+        match &self.branch {
+            Some(branch) => format!("{} [{branch}]", self.shortened_path),
+            None => self.shortened_path.clone(),
+        }
+    }
+
+    #[must_use]
+    pub fn exists(&self) -> bool {
+        let path = PathBuf::from(&self.expanded_path);
+        path.exists() && path.is_dir()
+    }
+
+    /// The name of the project's session: its own, or the name of a
+    /// session opened through another path to the same directory.
+    ///
+    /// # Errors
+    /// Returns an error if the multiplexer cannot be queried.
+    pub fn session_name(&self, mux: &dyn Multiplexer) -> Result<String> {
+        let sessions = get_tmux_sessions(mux)?;
+        if sessions
+            .iter()
+            .any(|session| session.tmux_display_path == self.tmux_display_path)
+        {
+            return Ok(self.tmux_display_path.clone());
+        }
+        Ok(sessions
+            .into_iter()
+            .find(|session| session.canonical_path == self.canonical_path)
+            .map_or_else(
+                || self.tmux_display_path.clone(),
+                |session| session.tmux_display_path,
+            ))
+    }
+
+    /// Creates the project's session if needed, then switches to it from
+    /// inside a client or attaches to it from outside.
+    ///
+    /// # Errors
+    /// Returns an error if the session cannot be created or reached.
+    pub fn attach(&self, mux: &dyn Multiplexer) -> Result<()> {
+        let tmux_session_name = &self.session_name(mux)?;
+
+        if !mux.session_exists(tmux_session_name)? {
+            mux.create_session(tmux_session_name, &self.expanded_path)?;
+        }
+
+        if mux.is_inside() {
+            let escaped_name = if tmux_session_name == "~" {
+                "\\~".to_string()
+            } else {
+                tmux_session_name.to_string()
+            };
+
+            mux.switch_client(&escaped_name)
+        } else {
+            mux.attach_session(tmux_session_name)
+        }
+    }
+}
+
+pub trait FilterExists {
+    fn filter_exists(&self) -> Vec<Project>;
+}
+
+impl FilterExists for Vec<Project> {
+    fn filter_exists(&self) -> Vec<Project> {
+        self.iter()
+            .filter(|project| project.exists())
+            .cloned()
+            .collect()
+    }
+}
+
+fn get_tmux_sessions(mux: &dyn Multiplexer) -> Result<Vec<Project>> {
+    Ok(mux
+        .sessions()?
+        .iter()
+        .map(|session| {
+            let mut project = Project::new(&session.name);
+            // Dots in the path become `_` in the session name, so a session
+            // tmux-leap started goes by the directory it was started in
+            let started_in = Project::new(&session.path);
+            if started_in.tmux_display_path == session.name {
+                project.canonical_path = started_in.canonical_path;
+            }
+            project
+        })
+        .collect())
+}
+
+/// Whether an entry's path is a glob pattern rather than one directory.
+#[must_use]
+pub fn is_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// The projects an entry's path stands for, after expanding `~`, `$VAR`,
+/// `${VAR}` and `${VAR:-default}`: the directory itself, or every directory
+/// a glob pattern like `~/work/*/services/*` or `~/src/**/.git/..` matches.
+///
+/// # Errors
+/// Returns an error if a variable is not set and has no default, or the
+/// pattern is invalid.
+pub fn expand_entry(path: &str) -> Result<Vec<Project>> {
+    let expanded = shellexpand::full(path)
+        .map_err(|e| Error::Config(format!("${} is not set", e.var_name)))?;
+    if !is_pattern(&expanded) {
+        return Ok(vec![Project::new(&expanded)]);
+    }
+    let matches = glob::glob(&expanded)
+        .map_err(|e| Error::Config(format!("invalid pattern \"{path}\": {e}")))?;
+    let mut seen = HashSet::new();
+    Ok(matches
+        .filter_map(std::result::Result::ok)
+        .filter(|path| path.is_dir())
+        .map(|path| normalize(&path))
+        .filter(|path| seen.insert(path.clone()))
+        .map(|path| Project::new(&path.to_string_lossy()))
+        .collect())
+}
+
+/// `dir` as an absolute path, taken relative to the current directory
+/// unless it is absolute already. Symlinks are resolved unless `logical`
+/// is set, which keeps the path as the shell shows it.
+///
+/// # Errors
+/// Returns an error if the current directory cannot be determined.
+pub fn absolute_path(dir: &str, logical: bool) -> Result<String> {
+    let current_dir = env::current_dir().map_err(|e| Error::io(".", e))?;
+    let path = Path::new(shellexpand::tilde(dir).as_ref()).to_path_buf();
+    let path = if logical {
+        // $PWD keeps the symlinks the shell went through to get here
+        let logical_dir = env::var_os("PWD")
+            .map(PathBuf::from)
+            .filter(|pwd| {
+                pwd.is_absolute() && fs::canonicalize(pwd).is_ok_and(|p| p == current_dir)
+            })
+            .unwrap_or(current_dir);
+        normalize(&logical_dir.join(path))
+    } else {
+        let path = current_dir.join(path);
+        fs::canonicalize(&path).unwrap_or_else(|_| normalize(&path))
+    };
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Resolves `.` and `..` components without touching the file system, so
+/// `~/src/app/.git/..` becomes `~/src/app`.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The projects a line of the projects file stands for: the matches of
+/// its path that aren't `--exclude`d, with its options applied but without
+/// the subdirectories of `--depth`. An entry with an unset variable stands
+/// for none, as it is meant for another machine; `doctor` still reports it.
+///
+/// # Errors
+/// Returns an error with the file and line number if the pattern is
+/// invalid.
+pub fn entry_projects(
+    Entry {
+        project: entry,
+        file,
+        number,
+    }: &Entry,
+) -> Result<Vec<Project>> {
+    if shellexpand::full(&entry.dir).is_err() {
+        return Ok(Vec::new());
+    }
+    let matches = expand_entry(&entry.dir)
+        .map_err(|e| Error::Config(format!("{}:{number}: {e}", file.display())))?;
+    Ok(matches
+        .into_iter()
+        .filter(|project| {
+            let root = Path::new(&project.expanded_path);
+            !entry.excludes(root, root)
+        })
+        .map(|project| {
+            match entry.depth {
+                Some(depth) => project.with_depth_source(&entry.dir, depth),
+                None => project,
+            }
+            .with_options(entry)
+        })
+        .collect())
+}
+
+/// Every project from the projects file and the files it includes, with
+/// patterns and `--depth` entries expanded and `--exclude`d directories
+/// left out, followed by the multiplexer's sessions. Entries with unset
+/// variables are skipped.
+///
+/// # Errors
+/// Returns an error if the projects file is malformed or `find` cannot run.
+pub fn get_projects(mux: &dyn Multiplexer) -> Result<Vec<Project>> {
+    let projects_file = paths()?.projects_file.clone();
+    let mut projects = Vec::new();
+    let mut unique_projects = HashSet::new();
+    for entry in read_entries(&projects_file)? {
+        for project in entry_projects(&entry)? {
+            let entry = &entry.project;
+            let Some(depth) = entry.depth else {
+                projects.push(project);
+                continue;
+            };
+            let root = PathBuf::from(&project.expanded_path);
+            let sub_dirs = Command::new("find")
+                .arg("-L")
+                .arg(&project.expanded_path)
+                .arg("-maxdepth")
+                .arg(depth.to_string())
+                .arg("-type")
+                .arg("d")
+                .output()
+                .map_err(|e| Error::spawn("find", e))?;
+            projects.push(project);
+            let sub_dirs = String::from_utf8_lossy(&sub_dirs.stdout);
+            for sub_dir in sub_dirs.lines() {
+                if entry.excludes(&root, Path::new(sub_dir)) {
+                    continue;
+                }
+                projects.push(
+                    Project::new(sub_dir)
+                        .with_depth_source(&entry.dir, depth)
+                        .with_options(entry),
+                );
+            }
+        }
+    }
+    let mut projects: Vec<Project> = projects
+        .into_iter()
+        .flat_map(|project| {
+            let worktrees = project.linked_worktrees();
+            std::iter::once(project).chain(worktrees)
+        })
+        .collect();
+    let sessions = get_tmux_sessions(mux)?;
+    let session_names: HashSet<&str> = sessions
+        .iter()
+        .map(|session| session.tmux_display_path.as_str())
+        .collect();
+    for project in &mut projects {
+        // A session opened through another path to the same directory is
+        // reused rather than listed next to the project
+        if session_names.contains(project.tmux_display_path.as_str()) {
+            continue;
+        }
+        if let Some(session) = sessions
+            .iter()
+            .find(|session| session.canonical_path == project.canonical_path)
+        {
+            project
+                .tmux_display_path
+                .clone_from(&session.tmux_display_path);
+        }
+    }
+    projects.extend(sessions);
+    Ok(projects
+        .into_iter()
+        .filter(|project| unique_projects.insert(project.canonical_path.clone()))
+        .collect())
+}
+
+/// Recently used projects in cache order, skipping the current session,
+/// missing directories and duplicates.
+///
+/// # Errors
+/// Returns an error if the current session cannot be determined.
+pub fn get_cached_projects(cache_file: &Path, mux: &dyn Multiplexer) -> Result<Vec<Project>> {
+    let current_session = mux.current_session()?;
+    let mut seen = HashSet::new();
+    Ok(read_lines(cache_file)
+        .unwrap_or_else(|_| vec![])
+        .into_iter()
+        .map(|line| Project::new(&line))
+        .filter(|project| {
+            current_session
+                .as_ref()
+                .is_none_or(|session| project.tmux_display_path != *session)
+        })
+        .filter(Project::exists)
+        .filter(|project| seen.insert(project.canonical_path.clone()))
+        .collect())
+}
+
+/// Every candidate in the order the picker presents them: recently used
+/// projects first, then the rest of the projects file and tmux sessions.
+///
+/// # Errors
+/// Returns an error if the projects or sessions cannot be loaded.
+pub fn get_candidates(mux: &dyn Multiplexer) -> Result<Vec<Project>> {
+    let cache_file = paths()?.cache_file.clone();
+    let projects = load_and_filter_projects(mux)?;
+    let by_path: HashMap<&str, &Project> = projects
+        .iter()
+        .map(|project| (project.canonical_path.as_str(), project))
+        .collect();
+    let mut seen = HashSet::new();
+    Ok(get_cached_projects(&cache_file, mux)?
+        .into_iter()
+        .map(|cached| {
+            by_path
+                .get(cached.canonical_path.as_str())
+                .map_or(cached, |&project| project.clone())
+        })
+        .chain(projects.iter().cloned())
+        .filter(|project| seen.insert(project.canonical_path.clone()))
+        .collect())
+}
+
+/// [`get_projects`], limited to directories that exist.
+///
+/// # Errors
+/// Returns an error if the projects or sessions cannot be loaded.
+pub fn load_and_filter_projects(mux: &dyn Multiplexer) -> Result<Vec<Project>> {
+    Ok(get_projects(mux)?.filter_exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiplexer::fake::FakeMultiplexer;
+    use std::fs;
+
+    #[test]
+    fn attach_creates_missing_session_then_attaches() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = Project::new(dir.path().to_str().unwrap());
+        let mux = FakeMultiplexer::new();
+
+        project.attach(&mux).unwrap();
+
+        let sessions = mux.session_list();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].name, project.tmux_display_path);
+        assert_eq!(sessions[0].path, project.expanded_path);
+        assert_eq!(sessions[0].attached, 1);
+        assert_eq!(mux.current(), Some(project.tmux_display_path));
+    }
+
+    #[test]
+    fn attach_switches_to_existing_session_from_inside() {
+        let project = Project::new("/srv/app");
+        let mux = FakeMultiplexer::new()
+            .with_session(&project.tmux_display_path, &project.expanded_path)
+            .inside("other");
+
+        project.attach(&mux).unwrap();
+
+        assert_eq!(mux.session_list().len(), 2);
+        assert_eq!(mux.current(), Some("/srv/app".to_string()));
+        let other = mux.session_list().into_iter().find(|s| s.name == "other");
+        assert_eq!(other.unwrap().attached, 0);
+    }
+
+    #[test]
+    fn attach_reuses_the_session_of_a_symlinked_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real");
+        let link = dir.path().join("link");
+        fs::create_dir(&real).unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        let via_link = Project::new(&format!("{}/../link/", real.display()));
+        let project = Project::new(real.to_str().unwrap());
+        let mux = FakeMultiplexer::new()
+            .with_session(&via_link.tmux_display_path, &via_link.expanded_path);
+
+        project.attach(&mux).unwrap();
+
+        assert_eq!(via_link.expanded_path, link.to_str().unwrap());
+        assert_eq!(via_link.canonical_path, project.canonical_path);
+        assert_eq!(mux.session_list().len(), 1);
+        assert_eq!(mux.current(), Some(via_link.tmux_display_path));
+    }
+
+    #[test]
+    fn entries_expand_variables_and_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        for repo in ["a/api", "a/web", "b/nested/docs"] {
+            fs::create_dir_all(dir.path().join(repo).join(".git")).unwrap();
+        }
+        fs::write(dir.path().join("a/notes"), "").unwrap();
+        let paths = |entry: &str| -> Vec<String> {
+            expand_entry(entry)
+                .unwrap()
+                .into_iter()
+                .map(|project| project.expanded_path)
+                .collect()
+        };
+
+        assert_eq!(
+            paths(&format!("${{TMUX_LEAP_UNSET_TEST:-{root}}}/a")),
+            [format!("{root}/a")]
+        );
+        assert_eq!(
+            paths(&format!("{root}/a/*")),
+            [format!("{root}/a/api"), format!("{root}/a/web")]
+        );
+        assert_eq!(
+            paths(&format!("{root}/**/.git/..")),
+            [
+                format!("{root}/a/api"),
+                format!("{root}/a/web"),
+                format!("{root}/b/nested/docs")
+            ]
+        );
+        assert!(matches!(
+            expand_entry("$TMUX_LEAP_UNSET_TEST/api"),
+            Err(Error::Config(message)) if message == "$TMUX_LEAP_UNSET_TEST is not set"
+        ));
+    }
+
+    #[test]
+    fn session_names_replace_dots() {
+        let project = Project::new("/srv/my.site/");
+        assert_eq!(project.expanded_path, "/srv/my.site");
+        assert_eq!(project.tmux_display_path, "/srv/my_site");
+    }
+}
@@ -0,0 +1,435 @@
+use crate::error::{Error, Result};
+use crate::files::read_lines;
+use crate::project::{is_pattern, Project};
+use glob::Pattern;
+use std::collections::{hash_map, HashMap};
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The key of a line that pulls in the entries of other files, as in
+/// `include = ["~/dotfiles/leap/team.toml", "./local.toml"]`.
+const INCLUDE_KEY: &str = "include";
+
+/// A line of the projects file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    /// An empty or whitespace-only line
+    Blank,
+    /// A line starting with `#`
+    Comment,
+    /// `include = ["FILE", ...]`, with the files as written
+    Include(Vec<String>),
+    Project(ProjectEntry),
+}
+
+impl Line {
+    /// Parses one line of the projects file.
+    ///
+    /// # Errors
+    /// Returns an error saying what is malformed, without the location.
+    pub fn parse(line: &str) -> Result<Self> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            Ok(Self::Blank)
+        } else if trimmed.starts_with('#') {
+            Ok(Self::Comment)
+        } else if let Some(files) = trimmed
+            .strip_prefix(INCLUDE_KEY)
+            .and_then(|rest| rest.trim_start().strip_prefix('='))
+        {
+            parse_file_list(files).map(Self::Include)
+        } else {
+            ProjectEntry::parse(trimmed).map(Self::Project)
+        }
+    }
+
+    /// The project entry of a well-formed project line, `None` otherwise.
+    #[must_use]
+    pub fn project(line: &str) -> Option<ProjectEntry> {
+        match Self::parse(line) {
+            Ok(Self::Project(entry)) => Some(entry),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the `["a", "b"]` list of an include line. Files are quoted with
+/// `"` or `'`, and `\` escapes the next character in `"` strings.
+fn parse_file_list(list: &str) -> Result<Vec<String>> {
+    let invalid = || {
+        Error::Config(format!(
+            "include needs a list of quoted files, like {INCLUDE_KEY} = [\"./team.toml\"]"
+        ))
+    };
+    let mut chars = list
+        .trim()
+        .strip_prefix('[')
+        .and_then(|list| list.strip_suffix(']'))
+        .ok_or_else(invalid)?
+        .chars()
+        .peekable();
+    let mut files = Vec::new();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(quote) = chars.next() else {
+            break;
+        };
+        if quote != '"' && quote != '\'' {
+            return Err(invalid());
+        }
+        let mut file = String::new();
+        loop {
+            match chars.next().ok_or_else(invalid)? {
+                c if c == quote => break,
+                '\\' if quote == '"' => file.push(chars.next().ok_or_else(invalid)?),
+                c => file.push(c),
+            }
+        }
+        files.push(file);
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.next() {
+            Some(',') => {}
+            None => break,
+            Some(_) => return Err(invalid()),
+        }
+    }
+    Ok(files)
+}
+
+/// A project line: a directory or pattern followed by options, as in
+/// `~/src --depth 2 --exclude node_modules --tag work`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProjectEntry {
+    pub dir: String,
+    pub depth: Option<u32>,
+    /// Patterns for directories to leave out of `--depth` and pattern matches
+    pub excludes: Vec<String>,
+    /// The session name to use instead of one derived from the path
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl ProjectEntry {
+    #[must_use]
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: dir.to_string(),
+            ..Self::default()
+        }
+    }
+
+    fn parse(line: &str) -> Result<Self> {
+        let options_start = if line.starts_with("--") {
+            Some(0)
+        } else {
+            line.find(" --")
+        };
+        let (dir, options) = match options_start {
+            Some(start) => (line[..start].trim_end(), &line[start..]),
+            None => (line, ""),
+        };
+        if dir.is_empty() {
+            return Err(Error::Config(
+                "missing directory before the options".to_string(),
+            ));
+        }
+        let mut entry = Self::new(dir);
+        let mut words = options.split_whitespace();
+        while let Some(option) = words.next() {
+            let mut value = || {
+                words
+                    .next()
+                    .ok_or_else(|| Error::Config(format!("{option} needs a value")))
+            };
+            match option {
+                "--depth" => {
+                    let value = value()?;
+                    let depth = value
+                        .parse()
+                        .map_err(|e| Error::Config(format!("invalid --depth \"{value}\": {e}")))?;
+                    if entry.depth.replace(depth).is_some() {
+                        return Err(Error::Config("--depth is given twice".to_string()));
+                    }
+                }
+                "--exclude" => {
+                    let value = value()?;
+                    Pattern::new(value).map_err(|e| {
+                        Error::Config(format!("invalid --exclude pattern \"{value}\": {e}"))
+                    })?;
+                    entry.excludes.push(value.to_string());
+                }
+                "--name" => {
+                    if entry.name.replace(value()?.to_string()).is_some() {
+                        return Err(Error::Config("--name is given twice".to_string()));
+                    }
+                }
+                "--tag" => entry.tags.push(value()?.to_string()),
+                _ => return Err(Error::Config(format!("unknown option \"{option}\""))),
+            }
+        }
+        if entry.name.is_some() && (entry.depth.is_some() || is_pattern(&entry.dir)) {
+            return Err(Error::Config(
+                "--name needs a single directory, not a pattern or --depth".to_string(),
+            ));
+        }
+        Ok(entry)
+    }
+
+    /// Formats the entry as a line of the projects file.
+    #[must_use]
+    pub fn to_line(&self) -> String {
+        let mut line = self.dir.clone();
+        if let Some(depth) = self.depth {
+            let _ = write!(line, " --depth {depth}");
+        }
+        for exclude in &self.excludes {
+            let _ = write!(line, " --exclude {exclude}");
+        }
+        if let Some(name) = &self.name {
+            let _ = write!(line, " --name {name}");
+        }
+        for tag in &self.tags {
+            let _ = write!(line, " --tag {tag}");
+        }
+        line
+    }
+
+    /// The directory the entry is for, with `~` expanded and symlinks
+    /// resolved, to tell whether two entries are for the same one.
+    #[must_use]
+    pub fn key(&self) -> String {
+        Project::new(&self.dir).canonical_path
+    }
+
+    /// Whether `dir`, found below `root`, is left out by an `--exclude`.
+    /// Patterns without a `/` match the name of `dir` or of a directory
+    /// between it and `root`; others match the full path of one of them.
+    #[must_use]
+    pub fn excludes(&self, root: &Path, dir: &Path) -> bool {
+        let below_root: Vec<&Path> = if dir == root || !dir.starts_with(root) {
+            vec![dir]
+        } else {
+            dir.ancestors()
+                .take_while(|ancestor| *ancestor != root)
+                .collect()
+        };
+        self.excludes.iter().any(|exclude| {
+            let expanded = shellexpand::tilde(exclude);
+            let Ok(pattern) = Pattern::new(&expanded) else {
+                return false;
+            };
+            below_root.iter().any(|path| {
+                if exclude.contains('/') {
+                    pattern.matches_path(path)
+                } else {
+                    path.file_name()
+                        .and_then(OsStr::to_str)
+                        .is_some_and(|name| pattern.matches(name))
+                }
+            })
+        })
+    }
+}
+
+/// A project entry from the projects file or a file it includes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub project: ProjectEntry,
+    pub file: PathBuf,
+    /// The 1-based line number in `file`
+    pub number: usize,
+}
+
+/// The file an `include` in `file` names, relative to `file`'s directory
+/// unless absolute.
+#[must_use]
+pub fn include_path(target: &str, file: &Path) -> PathBuf {
+    let target = PathBuf::from(shellexpand::tilde(target).as_ref());
+    match file.parent() {
+        Some(dir) if target.is_relative() => dir.join(target),
+        _ => target,
+    }
+}
+
+/// The project entries of `projects_file` in order, with `include` lines
+/// replaced by the entries of the files they name. An entry for a
+/// directory that is already listed replaces the earlier one, so later
+/// files override earlier ones. Missing files, included or not, have no
+/// entries.
+///
+/// # Errors
+/// Returns an error with the file and line number if a line is malformed
+/// or a file includes itself, directly or not.
+pub fn read_entries(projects_file: &Path) -> Result<Vec<Entry>> {
+    let mut collected = Vec::new();
+    collect_entries(projects_file, &mut Vec::new(), &mut collected)?;
+    let mut positions = HashMap::new();
+    let mut entries: Vec<Entry> = Vec::new();
+    for entry in collected {
+        match positions.entry(entry.project.key()) {
+            hash_map::Entry::Occupied(position) => entries[*position.get()] = entry,
+            hash_map::Entry::Vacant(position) => {
+                position.insert(entries.len());
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn collect_entries(
+    file: &Path,
+    including: &mut Vec<PathBuf>,
+    entries: &mut Vec<Entry>,
+) -> Result<()> {
+    let (Ok(canonical), Ok(lines)) = (fs::canonicalize(file), read_lines(file)) else {
+        return Ok(());
+    };
+    including.push(canonical);
+    for (index, line) in lines.iter().enumerate() {
+        let location = || format!("{}:{}", file.display(), index + 1);
+        match Line::parse(line) {
+            Err(e) => return Err(Error::Config(format!("{}: {e}", location()))),
+            Ok(Line::Blank | Line::Comment) => {}
+            Ok(Line::Include(targets)) => {
+                for target in targets {
+                    let included = include_path(&target, file);
+                    if fs::canonicalize(&included).is_ok_and(|path| including.contains(&path)) {
+                        return Err(Error::Config(format!(
+                            "{}: {} is already being included",
+                            location(),
+                            included.display()
+                        )));
+                    }
+                    collect_entries(&included, including, entries)?;
+                }
+            }
+            Ok(Line::Project(project)) => entries.push(Entry {
+                project,
+                file: file.to_path_buf(),
+                number: index + 1,
+            }),
+        }
+    }
+    including.pop();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_parse_into_entries_and_back() {
+        let line = "~/my src --depth 2 --exclude node_modules --exclude ~/my?src/old \
+                    --tag work --tag rust";
+
+        let Line::Project(entry) = Line::parse(line).unwrap() else {
+            panic!("not a project line");
+        };
+
+        assert_eq!(entry.dir, "~/my src");
+        assert_eq!(entry.depth, Some(2));
+        assert_eq!(entry.excludes, ["node_modules", "~/my?src/old"]);
+        assert_eq!(entry.tags, ["work", "rust"]);
+        assert_eq!(entry.to_line(), line);
+        assert_eq!(Line::parse("  # notes").unwrap(), Line::Comment);
+        assert_eq!(Line::parse(" ").unwrap(), Line::Blank);
+        assert_eq!(
+            Line::parse(r#"include = ["~/team.toml", './my "local".toml', "a\"b",]"#).unwrap(),
+            Line::Include(vec![
+                "~/team.toml".to_string(),
+                "./my \"local\".toml".to_string(),
+                "a\"b".to_string()
+            ])
+        );
+        assert_eq!(
+            Line::parse("include=[]").unwrap(),
+            Line::Include(Vec::new())
+        );
+    }
+
+    #[test]
+    fn malformed_lines_say_what_is_wrong() {
+        let error = |line: &str| match Line::parse(line) {
+            Err(Error::Config(message)) => message,
+            other => panic!("{line}: {other:?}"),
+        };
+
+        assert_eq!(error("~/x --depth"), "--depth needs a value");
+        assert!(error("~/x --depth x").starts_with("invalid --depth \"x\""));
+        assert_eq!(error("~/x --depth 1 --depth 2"), "--depth is given twice");
+        assert_eq!(error("~/x --color red"), "unknown option \"--color\"");
+        assert_eq!(
+            error("~/src/* --name src"),
+            "--name needs a single directory, not a pattern or --depth"
+        );
+        assert_eq!(error("--tag x"), "missing directory before the options");
+        for line in [
+            "include = ./team",
+            "include = [./team]",
+            r#"include = ["./team" "./local"]"#,
+            r#"include = ["./team"#,
+        ] {
+            assert!(error(line).starts_with("include needs a list"), "{line}");
+        }
+    }
+
+    #[test]
+    fn excludes_match_names_below_the_root_or_full_paths() {
+        let Some(entry) = Line::project("/src --depth 3 --exclude node_modules --exclude /src/a/b")
+        else {
+            panic!("not a project line");
+        };
+        let root = Path::new("/src");
+
+        assert!(entry.excludes(root, Path::new("/src/app/node_modules")));
+        assert!(entry.excludes(root, Path::new("/src/app/node_modules/x")));
+        assert!(entry.excludes(root, Path::new("/src/a/b/c")));
+        assert!(!entry.excludes(root, Path::new("/src/a")));
+        assert!(!entry.excludes(root, root));
+        assert!(entry.excludes(Path::new("/node_modules"), Path::new("/node_modules")));
+    }
+
+    #[test]
+    fn included_entries_are_overridden_by_later_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, contents: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        };
+        write(
+            "team",
+            "# shared\n/srv/api\n/srv/web --depth 1\ninclude = [\"./nested\"]\n",
+        );
+        write("nested", "/srv/docs\n");
+        let projects_file = write(
+            "projects",
+            "/srv/home\n\ninclude = [\"team\", \"missing\"]\n\n/srv/web\n",
+        );
+
+        let entries = read_entries(&projects_file).unwrap();
+
+        let dirs: Vec<&str> = entries.iter().map(|e| e.project.dir.as_str()).collect();
+        assert_eq!(dirs, ["/srv/home", "/srv/api", "/srv/web", "/srv/docs"]);
+        assert_eq!(entries[2].project.depth, None);
+        assert_eq!(
+            (entries[2].file.clone(), entries[2].number),
+            (projects_file, 5)
+        );
+
+        write("nested", "include = [\"projects\"]\n");
+        assert!(matches!(
+            read_entries(&dir.path().join("projects")),
+            Err(Error::Config(_))
+        ));
+        write("nested", "/srv/docs --depth x\n");
+        assert!(matches!(
+            read_entries(&dir.path().join("projects")),
+            Err(Error::Config(message)) if message.ends_with("nested:1: invalid --depth \"x\": invalid digit found in string")
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::error::{Error, Result};
+use crate::files::config_dir;
+use crate::git::git;
+use crate::template::{Placeholders, Template, Window, TEMPLATES_DIR};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Where projects created by tmux-leap go and how they start out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scaffold {
+    /// The directory new projects are created in
+    pub root: PathBuf,
+    /// Whether to run `git init` in a new project
+    pub git_init: bool,
+    /// The template new projects are copied from
+    pub template: Option<String>,
+}
+
+/// A project directory set up by [`Scaffold::create`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewProject {
+    pub dir: PathBuf,
+    /// Whether the directory was created, rather than reused as it was
+    pub created: bool,
+    /// The windows its session opens with, none for a plain session
+    pub windows: Vec<Window>,
+}
+
+impl Scaffold {
+    /// Creates the project directory `name` under the root, or reuses it if
+    /// it exists. Only a directory that is created here gets `git init` and
+    /// the template, after which the template's commands run. If setting it
+    /// up fails the directory is removed again, so a retry starts afresh.
+    ///
+    /// # Errors
+    /// Returns an error if `name` would leave the root, the template doesn't
+    /// exist or the directory cannot be set up.
+    pub fn create(&self, name: &str) -> Result<NewProject> {
+        let name = validate_name(name)?;
+        let dir = self.root.join(name);
+        if dir.is_dir() {
+            return Ok(NewProject {
+                dir,
+                created: false,
+                windows: Vec::new(),
+            });
+        }
+        let template = match &self.template {
+            Some(template) => Some(Template::load(
+                &config_dir()?.join(TEMPLATES_DIR),
+                template,
+            )?),
+            None => None,
+        };
+        fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+        match self.set_up(&dir, name, template.as_ref()) {
+            Ok(windows) => Ok(NewProject {
+                dir,
+                created: true,
+                windows,
+            }),
+            Err(e) => {
+                let _ = fs::remove_dir_all(&dir);
+                Err(e)
+            }
+        }
+    }
+
+    /// Sets up the new directory `dir` for the project `name` and returns
+    /// the windows of its session.
+    fn set_up(&self, dir: &Path, name: &str, template: Option<&Template>) -> Result<Vec<Window>> {
+        if self.git_init {
+            git(dir, &["init", "--quiet"])?;
+        }
+        let Some(template) = template else {
+            return Ok(Vec::new());
+        };
+        let project_name = Path::new(name)
+            .file_name()
+            .map_or(name.into(), |file_name| file_name.to_string_lossy());
+        template.apply(dir, &Placeholders::new(&project_name))
+    }
+}
+
+/// Checks that `name` is a path below the root, like `blog` or
+/// `clients/acme`, and returns it trimmed.
+fn validate_name(name: &str) -> Result<&str> {
+    let name = name.trim();
+    let path = Path::new(name);
+    if name.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(Error::Config(format!(
+            "invalid project name \"{name}\": expected a directory name below the projects root"
+        )));
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projects_are_created_below_the_root_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let scaffold = Scaffold {
+            root: dir.path().join("projects"),
+            git_init: false,
+            template: None,
+        };
+
+        let created = scaffold.create(" clients/acme ").unwrap();
+
+        assert_eq!(created.dir, dir.path().join("projects/clients/acme"));
+        assert!(created.dir.is_dir());
+        assert!(created.created);
+        assert_eq!(
+            scaffold.create("clients/acme").unwrap(),
+            NewProject {
+                created: false,
+                ..created
+            }
+        );
+        for name in ["", "../escape", "/tmp/x"] {
+            assert!(
+                matches!(scaffold.create(name), Err(Error::Config(_))),
+                "{name}"
+            );
+        }
+    }
+}
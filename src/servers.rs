@@ -0,0 +1,257 @@
+use crate::error::Result;
+use crate::multiplexer::Multiplexer;
+use crate::project::Project;
+use std::collections::HashSet;
+
+/// A tmux server taking part in the picker.
+#[derive(Debug, Clone)]
+pub struct Server<M> {
+    /// The label shown in the picker's server column
+    pub name: String,
+    pub mux: M,
+}
+
+/// Every server the picker lists sessions from. The first one is the
+/// primary server: projects without a session get one created there, and
+/// commands other than the picker only talk to it.
+#[derive(Debug, Clone)]
+pub struct Servers<M> {
+    servers: Vec<Server<M>>,
+}
+
+impl<M: Multiplexer> Servers<M> {
+    /// # Panics
+    /// Panics if `servers` is empty.
+    #[must_use]
+    pub fn new(servers: Vec<Server<M>>) -> Self {
+        assert!(!servers.is_empty(), "at least one server is required");
+        Self { servers }
+    }
+
+    #[must_use]
+    pub fn primary(&self) -> &Server<M> {
+        &self.servers[0]
+    }
+
+    /// Whether sessions from more than one server are shown, in which case
+    /// picker entries carry a server column.
+    #[must_use]
+    pub fn is_aggregated(&self) -> bool {
+        self.servers.len() > 1
+    }
+
+    /// The server whose client tmux-leap is running in, if any.
+    #[must_use]
+    pub fn current(&self) -> Option<&Server<M>> {
+        self.servers.iter().find(|server| server.mux.is_inside())
+    }
+
+    /// The server named in a picker entry, falling back to the primary one.
+    #[must_use]
+    pub fn get(&self, name: &str) -> &Server<M> {
+        self.servers
+            .iter()
+            .find(|server| server.name == name)
+            .unwrap_or_else(|| self.primary())
+    }
+
+    /// The sessions of every server other than the primary one, as
+    /// projects. The primary server's sessions are already part of
+    /// [`crate::project::get_projects`].
+    ///
+    /// # Errors
+    /// Returns an error if a server cannot be queried.
+    pub fn secondary_sessions(&self) -> Result<Vec<Project>> {
+        let mut sessions = Vec::new();
+        for server in &self.servers[1..] {
+            for session in server.mux.sessions()? {
+                sessions.push(Project::new(&session.name));
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// Picker entries for `projects`: one per server that already has a
+    /// session for the project, or one on the primary server otherwise.
+    /// Without aggregation an entry is just the project's display path.
+    ///
+    /// # Errors
+    /// Returns an error if a server cannot be queried.
+    pub fn entries(&self, projects: &[Project]) -> Result<Vec<String>> {
+        Ok(self.entries_by_project(projects)?.concat())
+    }
+
+    /// The picker entries of each of `projects`, as in [`Self::entries`].
+    ///
+    /// # Errors
+    /// Returns an error if a server cannot be queried.
+    pub fn entries_by_project(&self, projects: &[Project]) -> Result<Vec<Vec<String>>> {
+        if !self.is_aggregated() {
+            return Ok(projects
+                .iter()
+                .map(|project| vec![project.to_fzf_display()])
+                .collect());
+        }
+        let mut sessions = HashSet::new();
+        for server in &self.servers {
+            for session in server.mux.sessions()? {
+                sessions.insert((server.name.as_str(), session.name));
+            }
+        }
+        let mut entries = Vec::new();
+        for project in projects {
+            let hosts: Vec<&str> = self
+                .servers
+                .iter()
+                .map(|server| server.name.as_str())
+                .filter(|name| sessions.contains(&(*name, project.tmux_display_path.clone())))
+                .collect();
+            if hosts.is_empty() {
+                entries.push(vec![entry(project, &self.primary().name)]);
+            } else {
+                entries.push(hosts.into_iter().map(|name| entry(project, name)).collect());
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Splits a picker entry into its display path and server.
+    #[must_use]
+    pub fn parse_entry<'a>(&self, line: &'a str) -> (&'a str, &Server<M>) {
+        match line.rsplit_once('\t') {
+            Some((display, server)) if self.is_aggregated() => (display, self.get(server)),
+            _ => (line, self.primary()),
+        }
+    }
+
+    /// Leaps to `project` on `server`. When the current client belongs to
+    /// another server it is detached and replaced by a client of `server`.
+    ///
+    /// # Errors
+    /// Returns an error if the session cannot be created or reached.
+    pub fn leap(&self, project: &Project, server: &Server<M>) -> Result<()> {
+        match self.current() {
+            Some(current) if current.name != server.name => {
+                let session_name = &project.tmux_display_path;
+                if !server.mux.session_exists(session_name)? {
+                    server
+                        .mux
+                        .create_session(session_name, &project.expanded_path)?;
+                }
+                current
+                    .mux
+                    .detach_client_exec(&server.mux.attach_command(session_name))
+            }
+            _ => project.attach(&server.mux),
+        }
+    }
+}
+
+fn entry(project: &Project, server: &str) -> String {
+    format!("{}\t{server}", project.to_fzf_display())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiplexer::fake::FakeMultiplexer;
+
+    fn servers(work: FakeMultiplexer, personal: FakeMultiplexer) -> Servers<FakeMultiplexer> {
+        Servers::new(vec![
+            Server {
+                name: "work".to_string(),
+                mux: work,
+            },
+            Server {
+                name: "personal".to_string(),
+                mux: personal,
+            },
+        ])
+    }
+
+    #[test]
+    fn entries_list_every_server_with_a_session() {
+        let servers = servers(
+            FakeMultiplexer::new().with_session("/srv/api", "/srv/api"),
+            FakeMultiplexer::new()
+                .with_session("/srv/api", "/srv/api")
+                .with_session("/srv/blog", "/srv/blog"),
+        );
+        let projects = [
+            Project::new("/srv/api"),
+            Project::new("/srv/blog"),
+            Project::new("/srv/new"),
+        ];
+
+        assert_eq!(
+            servers.entries(&projects).unwrap(),
+            [
+                "/srv/api\twork",
+                "/srv/api\tpersonal",
+                "/srv/blog\tpersonal",
+                "/srv/new\twork"
+            ]
+        );
+        let (display, server) = servers.parse_entry("/srv/blog\tpersonal");
+        assert_eq!((display, server.name.as_str()), ("/srv/blog", "personal"));
+    }
+
+    #[test]
+    fn single_server_entries_have_no_server_column() {
+        let servers = Servers::new(vec![Server {
+            name: "default".to_string(),
+            mux: FakeMultiplexer::new(),
+        }]);
+
+        assert_eq!(
+            servers.entries(&[Project::new("/srv/api")]).unwrap(),
+            ["/srv/api"]
+        );
+        assert_eq!(servers.parse_entry("/srv/api").0, "/srv/api");
+    }
+
+    #[test]
+    fn leaping_within_the_current_server_switches_client() {
+        let work = FakeMultiplexer::new().inside("main");
+        let servers = servers(work.clone(), FakeMultiplexer::new());
+
+        servers
+            .leap(&Project::new("/srv/api"), servers.get("work"))
+            .unwrap();
+
+        assert_eq!(work.current(), Some("/srv/api".to_string()));
+        assert_eq!(work.detached_with(), None);
+    }
+
+    #[test]
+    fn leaping_across_servers_detaches_and_attaches_to_the_other() {
+        let work = FakeMultiplexer::new().inside("main");
+        let personal = FakeMultiplexer::new();
+        let servers = servers(work.clone(), personal.clone());
+
+        servers
+            .leap(&Project::new("/srv/blog"), servers.get("personal"))
+            .unwrap();
+
+        assert_eq!(personal.session_list()[0].name, "/srv/blog");
+        assert_eq!(
+            work.detached_with(),
+            Some(personal.attach_command("/srv/blog"))
+        );
+        assert_eq!(work.current(), None);
+    }
+
+    #[test]
+    fn leaping_from_outside_attaches_to_the_chosen_server() {
+        let work = FakeMultiplexer::new();
+        let personal = FakeMultiplexer::new();
+        let servers = servers(work.clone(), personal.clone());
+
+        servers
+            .leap(&Project::new("/srv/blog"), servers.get("personal"))
+            .unwrap();
+
+        assert_eq!(personal.current(), Some("/srv/blog".to_string()));
+        assert!(work.session_list().is_empty());
+    }
+}
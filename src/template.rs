@@ -0,0 +1,382 @@
+use crate::error::{Error, Result};
+use crate::files::{read_lines, unix_now};
+use crate::multiplexer::Multiplexer;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The directory below the config directory that holds the templates.
+pub const TEMPLATES_DIR: &str = "templates";
+/// The file in a template that says how to set it up, left out of copies.
+pub const MANIFEST: &str = ".tmux-leap";
+
+/// A directory copied into every project created with it, as in
+/// `~/.config/tmux-leap/templates/rust-cli`. Its [`MANIFEST`] lists
+/// commands to run in the new project and the windows of its session:
+///
+/// ```text
+/// run cargo init --name {{name}}
+/// window editor nvim
+/// window shell
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pub dir: PathBuf,
+    /// Shell commands run in a new project, in order
+    pub commands: Vec<String>,
+    pub windows: Vec<Window>,
+}
+
+/// A window of the session a template opens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    pub name: String,
+    /// Typed into the window's shell once it opens
+    pub command: Option<String>,
+}
+
+impl Template {
+    /// Loads template `name` from `templates_dir`.
+    ///
+    /// # Errors
+    /// Returns an error if there is no such template or its manifest is
+    /// malformed.
+    pub fn load(templates_dir: &Path, name: &str) -> Result<Self> {
+        let dir = templates_dir.join(name);
+        if name.is_empty() || name.starts_with('.') || name.contains('/') || !dir.is_dir() {
+            return Err(Error::Config(format!(
+                "no template \"{name}\" in {}",
+                templates_dir.display()
+            )));
+        }
+        let manifest = dir.join(MANIFEST);
+        let mut template = Self {
+            dir,
+            commands: Vec::new(),
+            windows: Vec::new(),
+        };
+        for (index, line) in read_lines(&manifest).unwrap_or_default().iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (directive, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let rest = rest.trim();
+            match directive {
+                "run" if !rest.is_empty() => template.commands.push(rest.to_string()),
+                "window" if !rest.is_empty() => {
+                    let (name, command) = rest.split_once(' ').unwrap_or((rest, ""));
+                    template.windows.push(Window {
+                        name: name.to_string(),
+                        command: Some(command.trim().to_string()).filter(|c| !c.is_empty()),
+                    });
+                }
+                "run" | "window" => {
+                    return Err(Error::Config(format!(
+                        "{}:{}: {directive} needs a value",
+                        manifest.display(),
+                        index + 1
+                    )))
+                }
+                _ => {
+                    return Err(Error::Config(format!(
+                        "{}:{}: unknown directive \"{directive}\"",
+                        manifest.display(),
+                        index + 1
+                    )))
+                }
+            }
+        }
+        Ok(template)
+    }
+
+    /// Copies the template into `dest`, filling in the placeholders of file
+    /// names and text files, then runs its commands there. Returns the
+    /// windows of the new project's session, with the placeholders of their
+    /// commands filled in. Commands get the values shell-quoted, so a name
+    /// like `x;rm -rf ~` stays a single word.
+    ///
+    /// # Errors
+    /// Returns an error if a file cannot be copied or a command fails.
+    pub fn apply(&self, dest: &Path, placeholders: &Placeholders) -> Result<Vec<Window>> {
+        copy_dir(&self.dir, dest, placeholders, true)?;
+        for command in &self.commands {
+            let command = placeholders.fill_command(command);
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .current_dir(dest)
+                .status()
+                .map_err(|e| Error::spawn("sh", e))?;
+            if !status.success() {
+                return Err(Error::CommandFailed { command, status });
+            }
+        }
+        Ok(self
+            .windows
+            .iter()
+            .map(|window| Window {
+                name: window.name.clone(),
+                command: window
+                    .command
+                    .as_deref()
+                    .map(|c| placeholders.fill_command(c)),
+            })
+            .collect())
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path, placeholders: &Placeholders, top: bool) -> Result<()> {
+    fs::create_dir_all(to).map_err(|e| Error::io(to, e))?;
+    for entry in fs::read_dir(from).map_err(|e| Error::io(from, e))? {
+        let entry = entry.map_err(|e| Error::io(from, e))?;
+        let name = entry.file_name();
+        if top && name == MANIFEST {
+            continue;
+        }
+        let source = entry.path();
+        let target = to.join(placeholders.fill(&name.to_string_lossy()));
+        if source.is_dir() {
+            copy_dir(&source, &target, placeholders, false)?;
+            continue;
+        }
+        let contents = fs::read(&source).map_err(|e| Error::io(&source, e))?;
+        // Binary files are copied as they are
+        let contents = match String::from_utf8(contents) {
+            Ok(text) => placeholders.fill(&text).into_bytes(),
+            Err(e) => e.into_bytes(),
+        };
+        fs::write(&target, contents).map_err(|e| Error::io(&target, e))?;
+        let permissions = fs::metadata(&source)
+            .map_err(|e| Error::io(&source, e))?
+            .permissions();
+        fs::set_permissions(&target, permissions).map_err(|e| Error::io(&target, e))?;
+    }
+    Ok(())
+}
+
+/// The values of `{{name}}`, `{{author}}` and `{{date}}` in a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placeholders {
+    pub name: String,
+    pub author: String,
+    /// The day the project is created, as `YYYY-MM-DD`
+    pub date: String,
+}
+
+impl Placeholders {
+    /// The values for a project named `name` created now by the current
+    /// user.
+    #[must_use]
+    pub fn new(name: &str) -> Self {
+        let realname = whoami::realname();
+        Self {
+            name: name.to_string(),
+            author: if realname.trim().is_empty() {
+                whoami::username()
+            } else {
+                realname
+            },
+            date: date(unix_now()),
+        }
+    }
+
+    #[must_use]
+    pub fn fill(&self, text: &str) -> String {
+        text.replace("{{name}}", &self.name)
+            .replace("{{author}}", &self.author)
+            .replace("{{date}}", &self.date)
+    }
+
+    /// Like [`Placeholders::fill`], but quotes each value for `sh`.
+    #[must_use]
+    pub fn fill_command(&self, command: &str) -> String {
+        command
+            .replace("{{name}}", &shell_quote(&self.name))
+            .replace("{{author}}", &shell_quote(&self.author))
+            .replace("{{date}}", &shell_quote(&self.date))
+    }
+}
+
+/// Quotes `value` as a single `sh` word, leaving it as it is if nothing in
+/// it needs quoting.
+fn shell_quote(value: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-.,/@%+=:".contains(c);
+    if !value.is_empty() && value.chars().all(plain) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+/// Formats a unix time as a UTC `YYYY-MM-DD` date.
+fn date(unix_time: u64) -> String {
+    // Howard Hinnant's civil_from_days, with days counted from 0000-03-01
+    let days = unix_time / 86400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Creates session `session_name` in `dir` with `windows`, the first of
+/// them taking the place of the session's own window.
+///
+/// # Errors
+/// Returns an error if the session or a window cannot be created.
+pub fn open_session(
+    mux: &dyn Multiplexer,
+    session_name: &str,
+    dir: &str,
+    windows: &[Window],
+) -> Result<()> {
+    mux.create_session(session_name, dir)?;
+    for (index, window) in windows.iter().enumerate() {
+        let window_id = if index == 0 {
+            mux.name_first_window(session_name, &window.name)?
+        } else {
+            mux.new_window(session_name, &window.name, dir)?
+        };
+        if let Some(command) = &window.command {
+            mux.send_command(&window_id, command)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiplexer::fake::FakeMultiplexer;
+
+    #[test]
+    fn templates_are_copied_with_placeholders_filled_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let template_dir = dir.path().join("templates/cli");
+        fs::create_dir_all(template_dir.join("src")).unwrap();
+        fs::write(
+            template_dir.join("src/{{name}}.rs"),
+            "// {{name}} by {{author}}\n",
+        )
+        .unwrap();
+        fs::write(template_dir.join("logo.bin"), [0xff, 0x00, 0xfe]).unwrap();
+        fs::write(
+            template_dir.join(MANIFEST),
+            "# set up\nrun echo {{date}} > created\nwindow editor vi {{name}}.rs\nwindow shell\n",
+        )
+        .unwrap();
+        let placeholders = Placeholders {
+            name: "blog".to_string(),
+            author: "Ada".to_string(),
+            date: "2024-02-29".to_string(),
+        };
+
+        let template = Template::load(&dir.path().join("templates"), "cli").unwrap();
+        let windows = template
+            .apply(&dir.path().join("blog"), &placeholders)
+            .unwrap();
+
+        let read = |file: &str| fs::read_to_string(dir.path().join("blog").join(file)).unwrap();
+        assert_eq!(read("src/blog.rs"), "// blog by Ada\n");
+        assert_eq!(read("created"), "2024-02-29\n");
+        assert_eq!(
+            fs::read(dir.path().join("blog/logo.bin")).unwrap(),
+            [0xff, 0x00, 0xfe]
+        );
+        assert!(!dir.path().join("blog").join(MANIFEST).exists());
+        assert_eq!(
+            windows,
+            [
+                Window {
+                    name: "editor".to_string(),
+                    command: Some("vi blog.rs".to_string())
+                },
+                Window {
+                    name: "shell".to_string(),
+                    command: None
+                }
+            ]
+        );
+        assert!(matches!(
+            Template::load(&dir.path().join("templates"), "web"),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn placeholders_in_commands_are_quoted() {
+        let dir = tempfile::tempdir().unwrap();
+        let template_dir = dir.path().join("templates/cli");
+        fs::create_dir_all(&template_dir).unwrap();
+        fs::write(
+            template_dir.join(MANIFEST),
+            "run echo {{name}} {{author}} > created\nwindow editor vi {{name}}\n",
+        )
+        .unwrap();
+        let placeholders = Placeholders {
+            name: "x;touch pwned".to_string(),
+            author: "Ann O'Brien".to_string(),
+            date: "2024-02-29".to_string(),
+        };
+
+        let template = Template::load(&dir.path().join("templates"), "cli").unwrap();
+        let windows = template
+            .apply(&dir.path().join("blog"), &placeholders)
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("blog/created")).unwrap(),
+            "x;touch pwned Ann O'Brien\n"
+        );
+        assert!(!dir.path().join("blog/pwned").exists());
+        assert_eq!(windows[0].command.as_deref(), Some("vi 'x;touch pwned'"));
+        assert_eq!(shell_quote("O'Brien"), r"'O'\''Brien'");
+    }
+
+    #[test]
+    fn sessions_open_with_the_template_windows() {
+        let mux = FakeMultiplexer::new();
+        let windows = [
+            Window {
+                name: "editor".to_string(),
+                command: Some("vi".to_string()),
+            },
+            Window {
+                name: "test".to_string(),
+                command: Some("cargo test".to_string()),
+            },
+        ];
+
+        open_session(&mux, "~/blog", "/srv/blog", &windows).unwrap();
+
+        let opened: Vec<(String, Vec<String>)> = mux
+            .windows("~/blog")
+            .into_iter()
+            .map(|window| (window.name, window.commands))
+            .collect();
+        assert_eq!(
+            opened,
+            [
+                ("editor".to_string(), vec!["vi".to_string()]),
+                ("test".to_string(), vec!["cargo test".to_string()])
+            ]
+        );
+    }
+
+    #[test]
+    fn dates_are_formatted_in_utc() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(1_709_251_199), "2024-02-29");
+    }
+}
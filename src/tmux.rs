@@ -1,154 +1,656 @@
+use crate::error::{Error, Result};
+use crate::multiplexer::{Multiplexer, SessionInfo};
 use std::env;
-use std::path::Path;
-use std::process::Command;
-
-#[must_use]
-pub fn is_inside_tmux() -> bool {
-    env::var("TMUX").is_ok()
-}
-
-/// Gets a list of all tmux sessions.
-///
-/// # Panics
-/// Panics if the `tmux list-sessions` command fails to execute.
-#[must_use]
-pub fn get_sessions() -> Vec<String> {
-    let output = Command::new("tmux")
-        .arg("list-sessions")
-        .arg("-F")
-        .arg("#{session_name}")
-        .output()
-        .expect("Failed to list tmux sessions");
-
-    if output.status.success() {
-        let sessions = String::from_utf8_lossy(&output.stdout);
-        sessions
-            .lines()
-            .map(std::string::ToString::to_string)
-            .collect()
-    } else {
-        Vec::new()
-    }
-}
-
-/// Checks if a tmux session with the given name exists.
-///
-/// # Panics
-/// Panics if the `tmux list-sessions` command fails to execute.
-#[must_use]
-pub fn session_exists(session_name: &str) -> bool {
-    let output = Command::new("tmux")
-        .arg("list-sessions")
-        .output()
-        .expect("Failed to list tmux sessions");
-
-    let sessions = String::from_utf8_lossy(&output.stdout);
-    sessions
-        .lines()
-        .any(|line| line.starts_with(&format!("{session_name}:")))
-}
-
-/// Creates a new tmux session with the given name in the specified directory.
-///
-/// # Panics
-/// Panics if:
-/// - The directory change fails
-/// - The `tmux new-session` command fails to execute
-#[must_use]
-pub fn create_session(session_name: &str, dir: &str) -> bool {
-    env::set_current_dir(Path::new(dir))
-        .unwrap_or_else(|_| panic!("Failed to change directory to {dir}"));
-    Command::new("tmux")
-        .arg("new-session")
-        .arg("-d")
-        .arg("-s")
-        .arg(session_name)
-        .status()
-        .expect("Failed to create new tmux session")
-        .success()
-}
-
-/// Switches the current tmux client to the specified session.
-///
-/// # Panics
-/// Panics if the `tmux switch-client` command fails to execute.
-#[must_use]
-pub fn switch_client(session_name: &str) -> bool {
-    Command::new("tmux")
-        .arg("switch-client")
-        .arg("-t")
-        .arg(session_name)
-        .status()
-        .expect("Failed to switch tmux client")
-        .success()
-}
-
-/// Attaches to the specified tmux session.
-///
-/// # Panics
-/// Panics if the `tmux attach-session` command fails to execute.
-#[must_use]
-pub fn attach_session(session_name: &str) -> bool {
-    Command::new("tmux")
-        .arg("attach-session")
-        .arg("-t")
-        .arg(session_name)
-        .env_remove("TMUX")
-        .status()
-        .expect("Failed to attach to tmux session")
-        .success()
-}
-
-/// Gets the name of the current tmux session.
-///
-/// # Panics
-/// Panics if the `tmux display-message` command fails to execute.
-#[must_use]
-pub fn get_current_session() -> Option<String> {
-    let output = Command::new("tmux")
-        .arg("display-message")
-        .arg("-p")
-        .arg("#S")
-        .output()
-        .expect("Failed to execute tmux command");
-    if output.status.success() {
-        let session_name = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        Some(session_name)
-    } else {
-        eprintln!("Failed to get current tmux session name");
-        None
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+
+/// Which tmux server to talk to, as selected by `-L` or `-S`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Socket {
+    /// A named socket in tmux's socket directory (`tmux -L`)
+    Name(String),
+    /// A socket at an explicit path (`tmux -S`)
+    Path(PathBuf),
+}
+
+impl Socket {
+    /// Whether the `$TMUX` value of a client belongs to this server.
+    fn matches(&self, tmux_env: &str) -> bool {
+        let client_socket = Path::new(tmux_env.split(',').next().unwrap_or_default());
+        match self {
+            Self::Name(name) => client_socket
+                .file_name()
+                .is_some_and(|file_name| file_name == name.as_str()),
+            Self::Path(path) => {
+                let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+                canonical(client_socket) == canonical(path)
+            }
+        }
     }
 }
 
-/// Attaches to the specified tmux session, replacing the current process.
-/// This is used when running from outside tmux and you want to process to persist.
-///
-/// # Panics
-/// Panics if the `exec` call fails or the tmux command is not found.
-pub fn attach_session_exec(session_name: &str, dir: &str) -> ! {
-    use std::os::unix::process::CommandExt;
-    use std::path::Path;
+/// The real tmux server. Commands run as one `tmux` process each, or with
+/// [`Tmux::with_control_mode`] over a single control-mode connection
+/// (`tmux -C`) that is opened on first use and shared by clones. Without a
+/// running server, or when the server's tmux predates the control flags it
+/// needs (3.2), it falls back to one process per command.
+#[derive(Debug, Clone, Default)]
+pub struct Tmux {
+    socket: Option<Socket>,
+    control: Arc<Mutex<Connection>>,
+}
+
+/// The state of the control-mode connection.
+#[derive(Debug, Default)]
+enum Connection {
+    /// Control mode is off
+    #[default]
+    Off,
+    /// Control mode is on but not connected yet
+    Pending,
+    Open(Control),
+    /// Connecting failed or the server went away, so it isn't tried again
+    Failed,
+}
+
+impl Tmux {
+    /// The default server, or the one the current client belongs to.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A specific server, or the default one for `None`.
+    #[must_use]
+    pub fn with_socket(socket: Option<Socket>) -> Self {
+        Self {
+            socket,
+            ..Self::default()
+        }
+    }
+
+    /// Sends commands over a control-mode connection instead. The
+    /// connection is a client attached to a session, so it runs the
+    /// server's `client-attached` and `client-detached` hooks.
+    #[must_use]
+    pub fn with_control_mode(self, enabled: bool) -> Self {
+        Self {
+            control: Arc::new(Mutex::new(if enabled {
+                Connection::Pending
+            } else {
+                Connection::Off
+            })),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub const fn socket(&self) -> Option<&Socket> {
+        self.socket.as_ref()
+    }
+
+    /// A label for this server, as shown in the picker's server column.
+    #[must_use]
+    pub fn name(&self) -> String {
+        match &self.socket {
+            Some(Socket::Name(name)) => name.clone(),
+            Some(Socket::Path(path)) => path.display().to_string(),
+            None => "default".to_string(),
+        }
+    }
+
+    fn socket_args(&self) -> Vec<String> {
+        match &self.socket {
+            Some(Socket::Name(name)) => vec!["-L".to_string(), name.clone()],
+            Some(Socket::Path(path)) => vec!["-S".to_string(), path.display().to_string()],
+            None => Vec::new(),
+        }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("tmux");
+        command.args(self.socket_args());
+        command
+    }
+
+    /// A command whose output gets parsed. `-u` keeps tmux from replacing
+    /// the tabs between fields with `_` when the locale isn't UTF-8.
+    fn query_command(&self) -> Command {
+        let mut command = self.command();
+        command.arg("-u");
+        command
+    }
+
+    /// Runs a tmux command, over the control connection when there is one.
+    ///
+    /// # Errors
+    /// Returns an error if tmux cannot be executed.
+    fn execute(&self, args: &[&str]) -> Result<Reply> {
+        // A newline would end the command early on the control connection
+        if !args.iter().any(|arg| arg.contains('\n')) {
+            let mut control = self
+                .control
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if matches!(*control, Connection::Pending) {
+                *control = Control::connect(self.query_command())
+                    .map_or(Connection::Failed, Connection::Open);
+            }
+            if let Connection::Open(connection) = &mut *control {
+                match connection.execute(args) {
+                    Ok(reply) => return Ok(reply),
+                    // The server went away, so carry on without it
+                    Err(_) => *control = Connection::Failed,
+                }
+            }
+        }
+        let output = self
+            .query_command()
+            .args(args)
+            .output()
+            .map_err(|e| Error::spawn("tmux", e))?;
+        Ok(Reply::from(output))
+    }
+
+    /// The session the control connection is attached to, or `None` when
+    /// there is no open connection.
+    fn control_session(&self) -> Option<String> {
+        let mut control = self
+            .control
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let Connection::Open(connection) = &mut *control else {
+            return None;
+        };
+        match connection.execute(&["display-message", "-p", "#{session_name}"]) {
+            Ok(reply) if reply.success => Some(reply.lines.concat()),
+            Ok(_) => None,
+            Err(_) => {
+                *control = Connection::Failed;
+                None
+            }
+        }
+    }
 
-    let escaped_name = if session_name == "~" {
+    /// Runs a tmux command, turning a failure into an error.
+    ///
+    /// # Errors
+    /// Returns an error if tmux cannot be executed or the command fails.
+    fn run(&self, action: &str, args: &[&str]) -> Result<Reply> {
+        let reply = self.execute(args)?;
+        if reply.success {
+            Ok(reply)
+        } else {
+            Err(Error::tmux(action, reply.lines.join("\n").as_bytes()))
+        }
+    }
+
+    /// The terminal type (`$TERM`) of the client tmux-leap runs in, or `None`
+    /// outside a client of this server.
+    ///
+    /// # Errors
+    /// Returns an error if the client cannot be queried.
+    pub fn client_terminal(&self) -> Result<Option<String>> {
+        let Some(client) = self.current_client()? else {
+            return Ok(None);
+        };
+        let reply = self.run(
+            "display-message",
+            &["display-message", "-p", "-c", &client, "#{client_termname}"],
+        )?;
+        Ok(Some(reply.lines.concat().trim().to_string()))
+    }
+
+    /// A target for the pane tmux-leap runs in, or for its session when
+    /// `$TMUX_PANE` isn't set. `None` outside a client of this server.
+    fn current_target(&self) -> Option<String> {
+        if !self.is_inside() {
+            return None;
+        }
+        env::var("TMUX_PANE")
+            .ok()
+            .filter(|pane| !pane.is_empty())
+            .or_else(|| {
+                let tmux_env = env::var("TMUX").ok()?;
+                let session_id = tmux_env.rsplit(',').next()?;
+                Some(format!("${session_id}"))
+            })
+    }
+
+    /// The client tmux-leap runs in: the most recently active terminal
+    /// client showing the current session. Commands sent over the control
+    /// connection would otherwise act on the control client itself.
+    ///
+    /// # Errors
+    /// Returns an error if the clients cannot be listed.
+    fn current_client(&self) -> Result<Option<String>> {
+        let Some(target) = self.current_target() else {
+            return Ok(None);
+        };
+        let reply = self.run(
+            "list-clients",
+            &[
+                "list-clients",
+                "-t",
+                &target,
+                "-F",
+                "#{client_control_mode}\t#{client_activity}\t#{client_name}",
+            ],
+        )?;
+        Ok(reply
+            .lines
+            .iter()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let control_mode = fields.next()?;
+                let activity: u64 = fields.next()?.parse().ok()?;
+                let name = fields.next()?;
+                (control_mode == "0").then(|| (activity, name.to_string()))
+            })
+            .max()
+            .map(|(_, name)| name))
+    }
+}
+
+/// The outcome of a tmux command: its output, or its error message if it
+/// failed.
+#[derive(Debug, Default)]
+struct Reply {
+    success: bool,
+    lines: Vec<String>,
+}
+
+impl From<Output> for Reply {
+    fn from(output: Output) -> Self {
+        let text = if output.status.success() {
+            &output.stdout
+        } else {
+            &output.stderr
+        };
+        Self {
+            success: output.status.success(),
+            lines: String::from_utf8_lossy(text)
+                .lines()
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+/// A control-mode client. It attaches to a session like any client, but
+/// with `ignore-size` so it never shrinks windows and `no-output` so pane
+/// output isn't streamed to it.
+#[derive(Debug)]
+struct Control {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Control {
+    /// Connects `command` in control mode, or returns `None` if the server
+    /// isn't running, has no sessions to attach to or is too old.
+    fn connect(mut command: Command) -> Option<Self> {
+        let mut child = command
+            .args(["-C", "attach-session", "-f", "ignore-size,no-output"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        };
+        let mut control = Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        };
+        // The attach-session given on the command line answers first
+        match read_reply(&mut control.stdout, ATTACH_FLAGS) {
+            Ok(reply) if reply.success => Some(control),
+            _ => None,
+        }
+    }
+
+    fn execute(&mut self, args: &[&str]) -> io::Result<Reply> {
+        let line: Vec<String> = args.iter().map(|arg| shell_quote(arg)).collect();
+        writeln!(self.stdin, "{}", line.join(" "))?;
+        self.stdin.flush()?;
+        read_reply(&mut self.stdout, COMMAND_FLAGS)
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The guard flags of the reply to the command given on the command line.
+const ATTACH_FLAGS: &str = "0";
+/// The guard flags of replies to commands written to the client.
+const COMMAND_FLAGS: &str = "1";
+
+/// Reads the next reply with the given guard flags, skipping notifications.
+/// A reply is the output between a `%begin` line and the `%end` or
+/// `%error` line with the same time and command number.
+fn read_reply(reader: &mut impl BufRead, flags: &str) -> io::Result<Reply> {
+    let mut guard = None;
+    let mut reply = Reply::default();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let line = line.trim_end_matches('\n');
+        let fields: Vec<&str> = line.split(' ').collect();
+        match (&guard, fields.as_slice()) {
+            (None, ["%begin", time, number, f]) if *f == flags => {
+                guard = Some((time.to_string(), number.to_string()));
+            }
+            (None, _) => {}
+            (Some((t, n)), [end @ ("%end" | "%error"), time, number, _])
+                if t == time && n == number =>
+            {
+                reply.success = *end == "%end";
+                return Ok(reply);
+            }
+            (Some(_), _) => reply.lines.push(line.to_string()),
+        }
+    }
+}
+
+/// Escapes a session name for `-t`; a bare `~` would be expanded by tmux.
+fn escape_target(session_name: &str) -> String {
+    if session_name == "~" {
         r"\~".to_string()
     } else {
         session_name.to_string()
-    };
-
-    // Set working directory before attaching
-    env::set_current_dir(Path::new(dir))
-        .unwrap_or_else(|_| panic!("Failed to change directory to {}", dir));
-
-    let mut command = std::process::Command::new("tmux");
-    command
-        .arg("attach-session")
-        .arg("-t")
-        .arg(&escaped_name)
-        .env_remove("TMUX");
-
-    // Replace the current process with tmux
-    let result = command.exec();
-    // exec replaces the process, so this never runs unless exec fails
-    eprintln!("Failed to attach to tmux session: {}", result);
-    std::process::exit(1);
+    }
+}
+
+/// Quotes `word` for `sh`, and equally for tmux's own command parser.
+fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Parses a `name\tattached\tpath` line from `tmux list-sessions`.
+fn parse_session_line(line: &str) -> Option<SessionInfo> {
+    let mut fields = line.rsplitn(3, '\t');
+    let path = fields.next()?;
+    let attached = fields.next()?;
+    let name = fields.next()?;
+    Some(SessionInfo {
+        name: name.to_string(),
+        attached: attached.parse().unwrap_or(0),
+        path: path.to_string(),
+    })
+}
+
+impl Multiplexer for Tmux {
+    fn is_inside(&self) -> bool {
+        env::var("TMUX").is_ok_and(|tmux_env| {
+            self.socket
+                .as_ref()
+                .is_none_or(|socket| socket.matches(&tmux_env))
+        })
+    }
+
+    fn sessions(&self) -> Result<Vec<SessionInfo>> {
+        let reply = self.execute(&[
+            "list-sessions",
+            "-F",
+            "#{session_name}\t#{session_attached}\t#{session_path}",
+        ])?;
+
+        // list-sessions fails when no server is running, which just means
+        // there are no sessions yet
+        if !reply.success {
+            return Ok(Vec::new());
+        }
+        let mut sessions: Vec<SessionInfo> = reply
+            .lines
+            .iter()
+            .filter_map(|line| parse_session_line(line))
+            .collect();
+
+        // Our own control client isn't someone looking at the session
+        if let Some(name) = self.control_session() {
+            if let Some(session) = sessions.iter_mut().find(|s| s.name == name) {
+                session.attached = session.attached.saturating_sub(1);
+            }
+        }
+        Ok(sessions)
+    }
+
+    fn create_session(&self, session_name: &str, dir: &str) -> Result<()> {
+        self.run(
+            "new-session",
+            &["new-session", "-d", "-s", session_name, "-c", dir],
+        )?;
+        Ok(())
+    }
+
+    fn name_first_window(&self, session_name: &str, window_name: &str) -> Result<String> {
+        let target = format!("={session_name}:^");
+        self.run(
+            "rename-window",
+            &["rename-window", "-t", &target, window_name],
+        )?;
+        let reply = self.run(
+            "display-message",
+            &["display-message", "-p", "-t", &target, "#{window_id}"],
+        )?;
+        Ok(reply.lines.concat().trim().to_string())
+    }
+
+    fn new_window(&self, session_name: &str, window_name: &str, dir: &str) -> Result<String> {
+        let target = format!("={session_name}:");
+        let reply = self.run(
+            "new-window",
+            &[
+                "new-window",
+                "-d",
+                "-P",
+                "-F",
+                "#{window_id}",
+                "-t",
+                &target,
+                "-n",
+                window_name,
+                "-c",
+                dir,
+            ],
+        )?;
+        Ok(reply.lines.concat().trim().to_string())
+    }
+
+    fn send_command(&self, window_id: &str, command: &str) -> Result<()> {
+        // -l types the command as it is, rather than as key names
+        self.run("send-keys", &["send-keys", "-t", window_id, "-l", command])?;
+        self.run("send-keys", &["send-keys", "-t", window_id, "Enter"])?;
+        Ok(())
+    }
+
+    fn kill_session(&self, session_name: &str) -> Result<()> {
+        // `=` disables prefix matching, so a similar name is never killed
+        let target = format!("={session_name}");
+        self.run("kill-session", &["kill-session", "-t", &target])?;
+        Ok(())
+    }
+
+    fn switch_client(&self, session_name: &str) -> Result<()> {
+        let client = self.current_client()?;
+        let mut args = vec!["switch-client"];
+        if let Some(client) = &client {
+            args.extend(["-c", client]);
+        }
+        args.extend(["-t", session_name]);
+        self.run("switch-client", &args)?;
+        Ok(())
+    }
+
+    fn attach_session(&self, session_name: &str) -> Result<()> {
+        let status = self
+            .command()
+            .arg("attach-session")
+            .arg("-t")
+            .arg(session_name)
+            .env_remove("TMUX")
+            .status()
+            .map_err(|e| Error::spawn("tmux", e))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::Tmux {
+                action: "attach-session".to_string(),
+                message: format!("could not attach to \"{session_name}\""),
+            })
+        }
+    }
+
+    fn attach_session_exec(&self, session_name: &str, dir: &str) -> Result<()> {
+        use std::os::unix::process::CommandExt;
+
+        let mut command = self.command();
+        command
+            .arg("attach-session")
+            .arg("-t")
+            .arg(escape_target(session_name))
+            .current_dir(dir)
+            .env_remove("TMUX");
+
+        // Replace the current process with tmux; exec only returns on failure
+        Err(Error::spawn("tmux", command.exec()))
+    }
+
+    fn current_session(&self) -> Result<Option<String>> {
+        let Some(target) = self.current_target() else {
+            return Ok(None);
+        };
+        let reply = self.run(
+            "display-message",
+            &["display-message", "-p", "-t", &target, "#S"],
+        )?;
+        Ok(Some(reply.lines.concat().trim().to_string()))
+    }
+
+    fn attach_command(&self, session_name: &str) -> String {
+        let mut words = vec!["exec".to_string(), "tmux".to_string()];
+        words.extend(self.socket_args().iter().map(|arg| shell_quote(arg)));
+        words.push("attach-session".to_string());
+        words.push("-t".to_string());
+        words.push(shell_quote(&escape_target(session_name)));
+        words.join(" ")
+    }
+
+    fn detach_client_exec(&self, shell_command: &str) -> Result<()> {
+        let client = self.current_client()?;
+        let mut args = vec!["detach-client", "-E", shell_command];
+        if let Some(client) = &client {
+            args.extend(["-t", client]);
+        }
+        self.run("detach-client", &args)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_session_names_containing_tabs() {
+        let session = parse_session_line("a\tb\t2\t/srv/a").unwrap();
+        assert_eq!(session.name, "a\tb");
+        assert_eq!(session.attached, 2);
+        assert_eq!(session.path, "/srv/a");
+    }
+
+    #[test]
+    fn control_replies_skip_notifications_and_other_guards() {
+        let transcript = "%begin 1700000000 263 0\n\
+            %end 1700000000 263 0\n\
+            %session-changed $0 main\n\
+            %begin 1700000000 268 1\n\
+            main\t1\t/srv\n\
+            %end 1700000000 999 1\n\
+            %end 1700000000 268 1\n\
+            %begin 1700000000 269 1\n\
+            can't find session: nope\n\
+            %error 1700000000 269 1\n";
+        let mut reader = transcript.as_bytes();
+
+        assert!(read_reply(&mut reader, ATTACH_FLAGS).unwrap().success);
+        let reply = read_reply(&mut reader, COMMAND_FLAGS).unwrap();
+        assert!(reply.success);
+        assert_eq!(reply.lines, ["main\t1\t/srv", "%end 1700000000 999 1"]);
+        let reply = read_reply(&mut reader, COMMAND_FLAGS).unwrap();
+        assert!(!reply.success);
+        assert_eq!(reply.lines, ["can't find session: nope"]);
+        assert!(read_reply(&mut reader, COMMAND_FLAGS).is_err());
+    }
+
+    #[test]
+    fn socket_names_match_the_client_socket_file() {
+        let tmux_env = "/tmp/tmux-1000/work,1234,0";
+        assert!(Socket::Name("work".to_string()).matches(tmux_env));
+        assert!(!Socket::Name("default".to_string()).matches(tmux_env));
+        assert!(Socket::Path(PathBuf::from("/tmp/tmux-1000/work")).matches(tmux_env));
+        assert!(!Socket::Path(PathBuf::from("/tmp/other")).matches(tmux_env));
+    }
+
+    #[test]
+    fn attach_commands_quote_sockets_and_sessions() {
+        let tmux = Tmux::with_socket(Some(Socket::Name("it's".to_string())));
+        assert_eq!(
+            tmux.attach_command("~"),
+            r"exec tmux '-L' 'it'\''s' attach-session -t '\~'"
+        );
+        assert_eq!(
+            Tmux::new().attach_command("~/a b"),
+            "exec tmux attach-session -t '~/a b'"
+        );
+    }
+
+    #[test]
+    fn sockets_are_passed_to_every_command() {
+        let args = |tmux: Tmux| {
+            tmux.command()
+                .get_args()
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert!(args(Tmux::new()).is_empty());
+        assert_eq!(
+            args(Tmux::with_socket(Some(Socket::Name("work".to_string())))),
+            ["-L", "work"]
+        );
+        assert_eq!(
+            args(Tmux::with_socket(Some(Socket::Path(PathBuf::from("/s"))))),
+            ["-S", "/s"]
+        );
+    }
+
+    #[test]
+    fn failed_control_connections_are_not_retried() {
+        let dir = tempfile::tempdir().unwrap();
+        let tmux =
+            Tmux::with_socket(Some(Socket::Path(dir.path().join("none")))).with_control_mode(true);
+
+        let _ = tmux.execute(&["list-sessions"]);
+
+        assert!(matches!(*tmux.control.lock().unwrap(), Connection::Failed));
+        assert!(matches!(
+            *Tmux::new().control.lock().unwrap(),
+            Connection::Off
+        ));
+    }
 }
@@ -0,0 +1,871 @@
+mod common;
+
+use common::{display, stdout, Env};
+
+#[test]
+fn add_records_current_and_explicit_directories() {
+    let env = Env::new();
+    let api = env.mkdir("work/api");
+    env.write_projects("");
+
+    assert!(env.leap(&["add"]).run().status.success());
+    assert!(env
+        .leap(&["add", &api, "--depth", "2"])
+        .run()
+        .status
+        .success());
+    assert!(env.leap(&["add", &api]).run().status.success());
+
+    assert_eq!(env.projects(), "~\n~/work/api\n");
+}
+
+#[test]
+fn list_expands_depth_entries() {
+    require_tmux!();
+    let env = Env::new();
+    env.mkdir("work/api/src");
+    env.mkdir("work/web");
+    env.write_projects("~/work --depth 1\n");
+
+    let output = env.leap(&["list"]).run();
+
+    assert!(output.status.success());
+    let mut listed: Vec<String> = stdout(&output).lines().map(str::to_string).collect();
+    listed.sort();
+    assert_eq!(listed, ["~/work", "~/work/api", "~/work/web"]);
+}
+
+#[test]
+fn only_control_mode_attaches_a_client() {
+    require_tmux!();
+    let env = Env::new();
+    env.mkdir("api");
+    env.write_projects("~/api\n");
+    let hook_log = env.home().join("attached");
+    assert!(env
+        .tmux(&["new-session", "-d", "-s", "main"])
+        .status
+        .success());
+    let hook = format!("run-shell 'echo attached >> {}'", hook_log.display());
+    assert!(env
+        .tmux(&["set-hook", "-g", "client-attached", &hook])
+        .status
+        .success());
+
+    let output = env.leap(&["list"]).run();
+
+    assert_eq!(stdout(&output), "~/api\nmain\n");
+    assert!(!hook_log.exists());
+    let output = env.leap(&["--control-mode", "list"]).run();
+    assert_eq!(stdout(&output), "~/api\nmain\n");
+    env.wait_for(|_| hook_log.exists());
+}
+
+#[test]
+fn other_control_clients_count_as_attached() {
+    require_tmux!();
+    let mut env = Env::new();
+    env.write_projects("");
+    assert!(env
+        .tmux(&["new-session", "-d", "-s", "main"])
+        .status
+        .success());
+    env.start_control_client("main");
+
+    for args in [
+        &["list", "--format", "tsv"][..],
+        &["--control-mode", "list", "--format", "tsv"],
+    ] {
+        let listed = stdout(&env.leap(args).run());
+        assert!(
+            listed.starts_with("main\tmain\tmain\ttrue\t1\t"),
+            "{listed}"
+        );
+    }
+}
+
+#[test]
+fn list_stops_quietly_when_the_reader_goes_away() {
+    require_tmux!();
+    let env = Env::new();
+    let entries: String = (0..20_000).map(|n| format!("~/project-{n}\n")).collect();
+    env.write_projects(&entries);
+
+    let output = env.leap(&["list", "--format", "null"]).run_until(1);
+
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stderr, b"");
+}
+
+#[test]
+fn delete_removes_the_selected_entry() {
+    let env = Env::new();
+    env.write_projects("~/a\n~/b --depth 1\n~/c\n");
+
+    let output = env.leap(&["delete"]).select("~/b --depth 1").run();
+
+    assert!(output.status.success());
+    assert_eq!(env.projects(), "~/a\n~/c\n");
+}
+
+#[test]
+fn delete_without_selection_changes_nothing() {
+    let env = Env::new();
+    env.write_projects("~/a\n");
+
+    let output = env.leap(&["delete"]).run();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(env.projects(), "~/a\n");
+}
+
+#[test]
+fn set_depth_reads_depth_for_the_selected_entry() {
+    let env = Env::new();
+    env.write_projects("~/b\n~/a\n");
+
+    let output = env.leap(&["set-depth"]).select("~/b").stdin("3\n").run();
+
+    assert!(output.status.success());
+    assert_eq!(env.projects(), "~/b --depth 3\n~/a\n");
+}
+
+#[test]
+fn set_depth_replaces_or_removes_an_existing_depth() {
+    let env = Env::new();
+    env.write_projects("~/a --depth 1\n");
+
+    env.leap(&["set-depth"])
+        .select("~/a --depth 1")
+        .stdin("2\n")
+        .run();
+    assert_eq!(env.projects(), "~/a --depth 2\n");
+
+    env.leap(&["set-depth"])
+        .select("~/a --depth 2")
+        .stdin("\n")
+        .run();
+    assert_eq!(env.projects(), "~/a\n");
+}
+
+#[test]
+fn delete_and_set_depth_take_paths_for_scripts() {
+    let env = Env::new();
+    env.write_projects("# mine\n~/a --tag x\n~/b\n~/c\n");
+
+    let set = env.leap(&["set-depth", "~/a", "2"]).run();
+    let deleted = env.leap(&["delete", "--yes", "~/b", "~/c/"]).run();
+
+    assert!(set.status.success(), "{set:?}");
+    assert!(deleted.status.success(), "{deleted:?}");
+    assert_eq!(env.projects(), "# mine\n~/a --depth 2 --tag x\n");
+    env.leap(&["set-depth", "~/a", "none"]).run();
+    assert_eq!(env.projects(), "# mine\n~/a --tag x\n");
+}
+
+#[test]
+fn delete_and_set_depth_reject_unknown_entries_and_depths() {
+    let env = Env::new();
+    env.write_projects("~/a\n~/b\n");
+
+    let unknown = env.leap(&["delete", "~/a", "~/nope"]).run();
+    let unconfirmed = env.leap(&["delete", "~/a"]).run();
+    let invalid = env.leap(&["set-depth", "~/a", "deep"]).run();
+    let prompted = env.leap(&["set-depth", "~/a"]).stdin("deep\n").run();
+
+    assert_eq!(unknown.status.code(), Some(1));
+    assert!(
+        String::from_utf8_lossy(&unknown.stderr).contains("\"~/nope\" is not in the projects file")
+    );
+    assert_eq!(unconfirmed.status.code(), Some(6));
+    assert!(String::from_utf8_lossy(&unconfirmed.stderr).contains("pass --yes"));
+    assert_eq!(invalid.status.code(), Some(2));
+    assert_eq!(prompted.status.code(), Some(6));
+    assert_eq!(env.projects(), "~/a\n~/b\n");
+}
+
+#[test]
+fn goto_from_inside_creates_session_and_switches() {
+    require_tmux!();
+    let mut env = Env::new();
+    let api = env.mkdir("api");
+    env.start_client("main");
+
+    let output = env.leap_inside(&["goto", &api]).run();
+
+    assert!(output.status.success(), "{output:?}");
+    assert!(env.sessions().contains(&"~/api".to_string()));
+    env.wait_for(|env| env.client_sessions() == ["~/api"]);
+}
+
+#[test]
+fn goto_reuses_an_existing_session() {
+    require_tmux!();
+    let mut env = Env::new();
+    let api = env.mkdir("api");
+    env.start_client("main");
+    env.tmux(&["new-session", "-d", "-s", "~/api", "-c", &api]);
+
+    let output = env.leap_inside(&["goto", &api]).run();
+
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(env.sessions().len(), 2);
+    env.wait_for(|env| env.client_sessions() == ["~/api"]);
+}
+
+#[test]
+fn goto_from_outside_creates_the_session() {
+    require_tmux!();
+    let env = Env::new();
+    let api = env.mkdir("api");
+
+    // There is no terminal to attach to here, only the session is checked
+    env.leap(&["goto", &api]).run();
+
+    assert_eq!(env.sessions(), ["~/api"]);
+}
+
+#[test]
+fn socket_options_pick_the_server() {
+    require_tmux!();
+    let env = Env::new();
+    let api = env.mkdir("api");
+    let web = env.mkdir("web");
+
+    env.leap(&["goto", &api, "--socket-name", "work"]).run();
+    env.leap(&["-L", "default", "goto", &web]).run();
+
+    assert_eq!(env.sessions_on("work"), ["~/api"]);
+    assert_eq!(env.sessions_on("default"), ["~/web"]);
+    assert!(env.sessions().is_empty());
+}
+
+#[test]
+fn picker_leaps_to_the_selection_and_caches_it() {
+    require_tmux!();
+    let mut env = Env::new();
+    let api = env.mkdir("api");
+    env.mkdir("web");
+    env.write_projects(&format!("{api}\n~/web\n"));
+    env.start_client("main");
+
+    let output = env.leap_inside(&[]).select("~/web").run();
+
+    assert!(output.status.success(), "{output:?}");
+    env.wait_for(|env| env.client_sessions() == ["~/web"]);
+    assert!(env.cache().starts_with("~/web\n"));
+}
+
+#[test]
+fn picker_offers_recent_projects_first() {
+    require_tmux!();
+    let mut env = Env::new();
+    let api = env.mkdir("api");
+    let web = env.mkdir("web");
+    env.write_projects(&format!("{api}\n{web}\n"));
+    std::fs::write(env.home().join(".projects_cache"), "~/web\n").unwrap();
+    env.start_client("main");
+
+    let output = env
+        .leap_inside(&[])
+        .select(&display(&env.home(), &api))
+        .run();
+
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(env.finder_log()[..2], ["~/web", "~/api"]);
+    assert_eq!(env.cache(), "~/api\n~/web\n");
+    let last_used =
+        std::fs::read_to_string(env.home().join(".local/state/tmux-leap/last-used")).unwrap();
+    assert!(last_used.starts_with("~/api\t"), "{last_used}");
+}
+
+#[test]
+fn picker_shows_git_status_without_matching_on_it() {
+    require_tmux!();
+    let mut env = Env::new();
+    let app = env.mkdir("app");
+    env.mkdir("notes");
+    env.git(&app, &["init", "--quiet", "-b", "main"]);
+    env.git(&app, &["commit", "--quiet", "--allow-empty", "-m", "init"]);
+    env.git(env.home(), &["clone", "--quiet", "app", "fork"]);
+    env.git(
+        env.home().join("fork"),
+        &["commit", "--quiet", "--allow-empty", "-m", "wip"],
+    );
+    std::fs::write(env.home().join("app/todo"), "").unwrap();
+    env.mkdir("app/docs");
+    env.write_projects("~/app\n~/fork\n~/notes\n~/app/docs\n");
+    env.start_client("main");
+
+    let output = env
+        .leap_inside(&[])
+        .env("FAKE_FZF_WAIT", "1")
+        .select("~/app/docs")
+        .run();
+
+    assert!(output.status.success(), "{output:?}");
+    let entries = ["~/app", "~/fork", "~/notes", "~/app/docs"];
+    assert_eq!(env.finder_log(), [entries, entries].concat());
+    assert_eq!(
+        env.finder_columns(),
+        ["", "", "", "", "main *", "main ↑1", "", ""]
+    );
+    assert_eq!(env.client_sessions(), ["~/app/docs"]);
+}
+
+#[test]
+fn picker_lists_projects_before_slow_statuses_are_read() {
+    require_tmux!();
+    let mut env = Env::new();
+    let app = env.mkdir("app");
+    env.git(&app, &["init", "--quiet"]);
+    env.slow_git_status(10);
+    env.write_projects("~/app\n");
+    env.start_client("main");
+
+    let started = std::time::Instant::now();
+    let output = env.leap_inside(&[]).select("~/app").run();
+
+    assert!(output.status.success(), "{output:?}");
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    assert_eq!(env.finder_columns(), [""]);
+    assert_eq!(env.client_sessions(), ["~/app"]);
+}
+
+#[test]
+fn query_leaps_to_a_single_match_and_filters_the_picker() {
+    require_tmux!();
+    let mut env = Env::new();
+    env.mkdir("api");
+    env.mkdir("api-old");
+    env.mkdir("web");
+    env.write_projects("~/api\n~/api-old\n~/web\n");
+    env.start_client("main");
+
+    let output = env.leap_inside(&["web"]).run();
+
+    assert!(output.status.success(), "{output:?}");
+    env.wait_for(|env| env.client_sessions() == ["~/web"]);
+    // leap_inside runs as if from a pane of main, so the client goes back there
+    env.tmux(&["switch-client", "-t", "main"]);
+
+    let output = env.leap_inside(&["api"]).select("~/api-old").run();
+
+    assert!(output.status.success(), "{output:?}");
+    env.wait_for(|env| env.client_sessions() == ["~/api-old"]);
+    assert_eq!(env.leap_inside(&["docs"]).run().status.code(), Some(3));
+    // Command names and aliases win over a query
+    let output = env.leap(&["l"]).run();
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("~/web"));
+    assert_eq!(env.leap(&["--first", "list"]).run().status.code(), Some(2));
+}
+
+#[test]
+fn first_leaps_to_the_best_match_without_a_picker() {
+    require_tmux!();
+    let mut env = Env::new();
+    env.mkdir("api");
+    env.mkdir("web");
+    env.write_projects("~/api\n~/web\n");
+    env.start_client("main");
+
+    let output = env.leap_inside(&["--first", "WEB"]).run();
+
+    assert!(output.status.success(), "{output:?}");
+    env.wait_for(|env| env.client_sessions() == ["~/web"]);
+    assert_eq!(
+        env.leap_inside(&["--first", "docs"]).run().status.code(),
+        Some(3)
+    );
+}
+
+#[test]
+fn picker_creates_a_project_from_the_query() {
+    require_tmux!();
+    let mut env = Env::new();
+    env.write_projects("");
+    env.start_client("main");
+
+    let output = env
+        .leap_inside(&["--new-root", "~/code", "--git-init"])
+        .press("blog", "ctrl-o")
+        .run();
+
+    assert!(output.status.success(), "{output:?}");
+    env.wait_for(|env| env.client_sessions() == ["~/code/blog"]);
+    assert!(env.home().join("code/blog/.git").is_dir());
+    assert_eq!(env.projects(), "~/code/blog\n");
+    assert!(env.cache().starts_with("~/code/blog\n"));
+    let output = env.leap_inside(&[]).press("../x", "ctrl-o").run();
+    assert_eq!(output.status.code(), Some(6));
+}
+
+#[test]
+fn new_copies_the_template_and_opens_its_windows() {
+    require_tmux!();
+    let mut env = Env::new();
+    let template = env.mkdir(".config/tmux-leap/templates/cli");
+    std::fs::write(format!("{template}/README.md"), "# {{name}}\n").unwrap();
+    std::fs::write(
+        format!("{template}/.tmux-leap"),
+        "run touch {{name}}.log\nwindow editor\nwindow test echo {{name}} > window.txt\n",
+    )
+    .unwrap();
+    env.write_projects("");
+    env.start_client("main");
+
+    let output = env
+        .leap_inside(&["new", "tools/blog", "--template", "cli"])
+        .run();
+
+    assert!(output.status.success(), "{output:?}");
+    env.wait_for(|env| env.client_sessions() == ["~/projects/tools/blog"]);
+    let blog = env.home().join("projects/tools/blog");
+    assert_eq!(
+        std::fs::read_to_string(blog.join("README.md")).unwrap(),
+        "# blog\n"
+    );
+    assert!(blog.join("blog.log").exists());
+    assert!(!blog.join(".tmux-leap").exists());
+    assert_eq!(env.projects(), "~/projects/tools/blog\n");
+    let windows = env.tmux(&[
+        "list-windows",
+        "-t",
+        "=~/projects/tools/blog",
+        "-F",
+        "#{window_name}",
+    ]);
+    assert_eq!(stdout(&windows), "editor\ntest\n");
+    env.wait_for(|_| {
+        std::fs::read_to_string(blog.join("window.txt")).is_ok_and(|text| text == "blog\n")
+    });
+    let output = env
+        .leap_inside(&["new", "tools/blog", "--template", "cli"])
+        .run();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        stdout(&output),
+        "Using existing \"~/projects/tools/blog\"\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("template \"cli\" was not applied"));
+    // Only new and the picker set up projects
+    assert_eq!(
+        env.leap(&["list", "--template", "cli"]).run().status.code(),
+        Some(2)
+    );
+    assert_eq!(
+        env.leap(&["--template", "cli", "list"]).run().status.code(),
+        Some(2)
+    );
+
+    let output = env.leap(&["new", "web", "--template", "missing"]).run();
+    assert_eq!(output.status.code(), Some(6));
+    assert!(!env.home().join("projects/web").exists());
+    let broken = env.mkdir(".config/tmux-leap/templates/broken");
+    std::fs::write(format!("{broken}/.tmux-leap"), "run false\n").unwrap();
+    let output = env.leap(&["new", "web", "--template", "broken"]).run();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!env.home().join("projects/web").exists());
+}
+
+#[test]
+fn clone_checks_out_into_host_and_path_and_leaps() {
+    require_tmux!();
+    let mut env = Env::new();
+    let remote = env.mkdir("remote/me/tool.git");
+    env.git(&remote, &["init", "--quiet", "--bare"]);
+    env.write_projects("");
+    env.start_client("main");
+    let url = format!("file://{remote}");
+
+    let output = env.leap_inside(&["clone", &url]).run();
+
+    assert!(output.status.success(), "{output:?}");
+    let clone = format!("src/localhost{}", remote.trim_end_matches(".git"));
+    assert!(env.home().join(&clone).join(".git").is_dir());
+    assert_eq!(env.projects(), format!("~/{clone}\n"));
+    env.wait_for(|env| env.client_sessions()[0].ends_with("/remote/me/tool"));
+
+    env.tmux(&["switch-client", "-t", "main"]);
+    let output = env.leap_inside(&["clone", &url, "--root", "~/code"]).run();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(env.projects().lines().count(), 2);
+    assert_eq!(
+        env.leap(&["clone", "not a url"]).run().status.code(),
+        Some(6)
+    );
+}
+
+#[test]
+fn worktrees_are_listed_by_branch_and_managed() {
+    require_tmux!();
+    let mut env = Env::new();
+    let app = env.home().join("app");
+    env.mkdir("app");
+    env.git(&app, &["init", "--quiet"]);
+    env.git(&app, &["commit", "--quiet", "--allow-empty", "-m", "init"]);
+    env.write_projects("~/app\n");
+    env.start_client("main");
+
+    let output = env
+        .leap_inside(&["worktree", "add", "feature/login"])
+        .current_dir(&app)
+        .run();
+
+    assert!(output.status.success(), "{output:?}");
+    env.wait_for(|env| env.client_sessions() == ["~/app@feature/login"]);
+    assert!(env.home().join("app@feature-login").is_dir());
+    let listed = stdout(&env.leap(&["list", "--format", "tsv"]).run());
+    assert!(
+        listed.contains("\t~/app@feature-login\t~/app@feature/login\t"),
+        "{listed}"
+    );
+    assert!(listed.contains("\tfeature/login\n"), "{listed}");
+    let output = env
+        .leap(&["resolve", "~/app@feature-login [feature/login]"])
+        .run();
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "~/app@feature/login\t{}\n",
+            env.home().join("app@feature-login").display()
+        )
+    );
+
+    let output = env
+        .leap(&["worktree", "remove", "feature/login"])
+        .current_dir(&app)
+        .run();
+
+    assert!(output.status.success(), "{output:?}");
+    assert!(!env.home().join("app@feature-login").exists());
+    assert!(!env.sessions().contains(&"~/app@feature/login".to_string()));
+    assert_eq!(
+        env.leap(&["worktree", "remove", "feature/login"])
+            .current_dir(&app)
+            .run()
+            .status
+            .code(),
+        Some(1)
+    );
+    assert_eq!(
+        env.leap(&["worktree", "add", "--", "--orphan"])
+            .current_dir(&app)
+            .run()
+            .status
+            .code(),
+        Some(6)
+    );
+}
+
+#[test]
+fn picker_without_selection_exits_with_no_selection() {
+    require_tmux!();
+    let mut env = Env::new();
+    env.mkdir("api");
+    env.write_projects("~/api\n");
+    env.start_client("main");
+
+    let output = env.leap_inside(&[]).run();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(env.sessions(), ["main"]);
+}
+
+#[test]
+fn picker_moves_the_client_to_a_session_on_another_server() {
+    require_tmux!();
+    let mut env = Env::new();
+    let api = env.mkdir("api");
+    env.mkdir("web");
+    env.write_projects("~/web\n");
+    env.tmux_on("other", &["new-session", "-d", "-s", "~/api", "-c", &api]);
+    env.start_client("main");
+
+    let output = env
+        .leap_inside(&["-L", common::SOCKET, "-L", "other"])
+        .select("~/api\tother")
+        .run();
+
+    assert!(output.status.success(), "{output:?}");
+    assert!(env.finder_log().contains(&"~/web\tleap-test".to_string()));
+    env.wait_for(|env| {
+        let clients = env.tmux_on("other", &["list-clients", "-F", "#{client_session}"]);
+        stdout(&clients).trim() == "~/api"
+    });
+    assert!(env.cache().starts_with("~/api\n"));
+}
+
+#[test]
+fn entries_with_unset_variables_are_skipped_but_reported() {
+    require_tmux!();
+    let env = Env::new();
+    env.mkdir("api");
+    env.write_projects("~/api\n$LEAP_TEST_UNSET/web\n");
+
+    let output = env.leap(&["list"]).run();
+
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(stdout(&output), "~/api\n");
+    let output = env.leap(&["doctor"]).run();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains(".projects:2: $LEAP_TEST_UNSET is not set"));
+}
+
+#[test]
+fn structured_status_expands_entries_like_list() {
+    require_tmux!();
+    let env = Env::new();
+    env.mkdir("svc/api");
+    env.mkdir("svc/web");
+    env.mkdir("svc/old");
+    env.write_projects("~/svc/* --exclude old\n$LEAP_TEST_UNSET/web\n");
+
+    let listed = stdout(&env.leap(&["list", "--format", "null"]).run());
+    let output = env.leap(&["status", "--format", "null"]).run();
+
+    assert!(output.status.success(), "{output:?}");
+    let home = env.home().display().to_string();
+    assert_eq!(listed, format!("{home}/svc/api\0{home}/svc/web\0"));
+    assert_eq!(stdout(&output), listed);
+}
+
+#[test]
+fn doctor_reports_problems_in_the_projects_file() {
+    let env = Env::new();
+    env.mkdir("api");
+    env.write_projects("~/api\n~/gone\n~/api --depth x\n");
+
+    let output = env.leap(&["doctor"]).run();
+
+    assert_eq!(output.status.code(), Some(1));
+    let report = stdout(&output);
+    assert!(
+        report.contains(".projects:2: ~/gone is not a directory"),
+        "{report}"
+    );
+    assert!(
+        report.contains(".projects:3: invalid --depth \"x\""),
+        "{report}"
+    );
+}
+
+#[test]
+fn prune_dry_run_only_reports() {
+    let env = Env::new();
+    env.mkdir("api");
+    env.write_projects("~/api\n~/gone --depth 1\n");
+
+    let output = env.leap(&["prune", "--dry-run"]).run();
+
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        stdout(&output),
+        "Would remove \"~/gone --depth 1\" from .projects\n"
+    );
+    assert_eq!(env.projects(), "~/api\n~/gone --depth 1\n");
+}
+
+#[test]
+fn included_files_add_projects_and_survive_pruning() {
+    let env = Env::new();
+    env.mkdir("api");
+    env.mkdir("web");
+    std::fs::write(env.home().join("team.toml"), "~/api\n").unwrap();
+    std::fs::write(env.home().join("local.toml"), "~/web\n").unwrap();
+    env.write_projects("include = [\"~/team.toml\", \"./local.toml\"]\n~/gone\n");
+
+    let candidates = stdout(&env.leap(&["candidates"]).run());
+    let output = env.leap(&["prune"]).run();
+
+    assert_eq!(candidates, "~/api\n~/web\n");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        env.projects(),
+        "include = [\"~/team.toml\", \"./local.toml\"]\n"
+    );
+}
+
+#[test]
+fn prune_removes_dead_entries_and_sessions() {
+    require_tmux!();
+    let env = Env::new();
+    let api = env.mkdir("api");
+    let gone = env.mkdir("gone");
+    env.write_projects("~/api\n~/gone\n");
+    std::fs::write(env.home().join(".projects_cache"), "~/gone\n~/api\n").unwrap();
+    env.tmux(&["new-session", "-d", "-s", "~/api", "-c", &api]);
+    env.tmux(&["new-session", "-d", "-s", "~/gone", "-c", &gone]);
+    std::fs::remove_dir(&gone).unwrap();
+
+    let output = env.leap(&["prune", "--sessions"]).run();
+
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(env.projects(), "~/api\n");
+    assert_eq!(env.cache(), "~/api\n");
+    assert_eq!(env.sessions(), ["~/api"]);
+}
+
+#[test]
+fn undo_restores_the_version_before_a_change_and_can_be_undone() {
+    let env = Env::new();
+    env.write_projects("~/a\n~/b\n");
+    env.leap(&["delete"]).select("~/b").run();
+    env.leap(&["add", "~/c"]).run();
+
+    let history: Vec<(String, String)> = stdout(&env.leap(&["history"]).run())
+        .lines()
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            (fields[0].to_string(), fields[2].to_string())
+        })
+        .collect();
+    assert_eq!(
+        history,
+        [
+            ("2".to_string(), "-~/c".to_string()),
+            ("1".to_string(), "+~/b".to_string())
+        ]
+    );
+
+    assert!(env.leap(&["undo"]).run().status.success());
+    assert_eq!(env.projects(), "~/a\n");
+    assert!(env.leap(&["undo"]).run().status.success());
+    assert_eq!(env.projects(), "~/a\n~/c\n");
+    assert!(env.leap(&["undo", "1"]).run().status.success());
+    assert_eq!(env.projects(), "~/a\n~/b\n");
+    assert_eq!(stdout(&env.leap(&["history"]).run()).lines().count(), 5);
+    assert_eq!(env.leap(&["undo", "9"]).run().status.code(), Some(1));
+}
+
+#[test]
+fn files_live_in_xdg_directories_without_legacy_files() {
+    let env = Env::new();
+    let home = env.home();
+
+    env.leap(&["add", "~/api"]).run();
+    let output = env
+        .leap(&["add", "~/web"])
+        .env("XDG_CONFIG_HOME", home.join("xdg"))
+        .run();
+
+    assert!(output.status.success(), "{output:?}");
+    let read = |path: &str| std::fs::read_to_string(home.join(path)).unwrap();
+    assert_eq!(read(".config/tmux-leap/projects"), "~/api\n");
+    assert_eq!(read("xdg/tmux-leap/projects"), "~/web\n");
+    assert!(home.join(".local/state/tmux-leap/history").is_dir());
+    assert!(!home.join(".projects").exists());
+}
+
+#[test]
+fn config_flag_and_environment_override_the_projects_file() {
+    let env = Env::new();
+    let home = env.home();
+    env.write_projects("~/legacy\n");
+
+    env.leap(&["add", "~/api", "--config", "flag"]).run();
+    env.leap(&["add", "~/web"])
+        .env("TMUX_LEAP_CONFIG", home.join("from-env"))
+        .env("TMUX_LEAP_STATE", home.join("state"))
+        .run();
+
+    let read = |path: &str| std::fs::read_to_string(home.join(path)).unwrap();
+    assert_eq!(read("flag"), "~/api\n");
+    assert_eq!(read("from-env"), "~/web\n");
+    assert!(home.join("state/history").is_dir());
+    assert!(home.join("state/lock").is_file());
+    assert!(home.join(".local/state/tmux-leap/lock").is_file());
+    assert_eq!(env.projects(), "~/legacy\n");
+}
+
+#[test]
+fn profiles_keep_separate_project_lists() {
+    let env = Env::new();
+    let home = env.home();
+    env.write_projects("~/legacy\n");
+
+    env.leap(&["add", "~/api", "--profile", "work"]).run();
+    env.leap(&["add", "~/blog"])
+        .env("TMUX_LEAP_PROFILE", "personal")
+        .run();
+    let output = env.leap(&["profiles", "-p", "work"]).run();
+
+    let read = |path: &str| std::fs::read_to_string(home.join(path)).unwrap();
+    assert_eq!(read(".config/tmux-leap/profiles/work"), "~/api\n");
+    assert_eq!(read(".config/tmux-leap/profiles/personal"), "~/blog\n");
+    assert!(home
+        .join(".local/state/tmux-leap/profiles/work/history")
+        .is_dir());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "  default\n  personal\n* work\n"
+    );
+    assert_eq!(env.projects(), "~/legacy\n");
+    let output = env.leap(&["list", "--profile", "default"]).run();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("~/legacy"));
+}
+
+#[test]
+fn projects_file_options_shape_the_project_list() {
+    require_tmux!();
+    let env = Env::new();
+    env.mkdir("work/api/node_modules/pkg");
+    env.mkdir("work/web");
+    env.mkdir("infra");
+    env.write_projects(
+        "# work\n\n~/work --depth 2 --exclude node_modules --tag work\n~/infra --name ops\n",
+    );
+
+    let listed = stdout(&env.leap(&["list", "--tag", "work"]).run());
+    let infra = stdout(&env.leap(&["list", "--format", "tsv"]).run());
+
+    let mut listed: Vec<&str> = listed.lines().collect();
+    listed.sort_unstable();
+    assert_eq!(listed, ["~/work", "~/work/api", "~/work/web"]);
+    assert!(infra.contains("\t~/infra\tops\t"), "{infra}");
+}
+
+#[test]
+fn malformed_entries_are_reported_with_their_line() {
+    let env = Env::new();
+    env.write_projects("# comment\n~/a --dpth 1\n");
+
+    let output = env.leap(&["list"]).run();
+
+    assert_eq!(output.status.code(), Some(6));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains(".projects:2: unknown option \"--dpth\"")
+    );
+}
+
+#[test]
+fn symlinked_and_relative_paths_are_one_project() {
+    let env = Env::new();
+    env.mkdir("work/api");
+    std::os::unix::fs::symlink(env.home().join("work/api"), env.home().join("api")).unwrap();
+    env.write_projects("");
+
+    for dir in ["work/../work/api", "api", "./api/"] {
+        assert!(env.leap(&["add", dir]).run().status.success());
+    }
+    assert_eq!(env.projects(), "~/work/api\n");
+
+    env.write_projects("");
+    assert!(env
+        .leap(&["add", "api", "--logical"])
+        .run()
+        .status
+        .success());
+    assert!(env.leap(&["add", "work/api"]).run().status.success());
+    assert_eq!(env.projects(), "~/api\n");
+
+    require_tmux!();
+    env.write_projects("~/api\n~/work --depth 1\n");
+    let output = env.leap(&["list"]).run();
+
+    assert!(output.status.success());
+    let mut listed: Vec<String> = stdout(&output).lines().map(str::to_string).collect();
+    listed.sort();
+    assert_eq!(listed, ["~/api", "~/work"]);
+}
@@ -0,0 +1,453 @@
+//! Harness for running tmux-leap end to end against a private tmux server,
+//! a temporary `HOME` and a scripted stand-in for fzf.
+//!
+//! Each test gets its own `TMUX_TMPDIR` and talks to the server on the
+//! [`SOCKET`] name in it, so nothing touches the user's own tmux.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// Picks the line named by `$FAKE_FZF_SELECT` from stdin, logging every line
+/// it sees to `$FAKE_FZF_LOG`. Exits like fzf does when nothing is chosen.
+/// With `--filter` or `--select-1`, lines match a query by containing it.
+/// With `--print-query`, `$FAKE_FZF_QUERY` is printed as the typed query and
+/// `$FAKE_FZF_KEY` is pressed instead of choosing a line. With `--ansi`, the
+/// last tab-separated column is left out of what is logged and matched, and
+/// logged to `$FAKE_FZF_COLUMN_LOG` without its colours instead. A
+/// `reload-sync` bound to `load` runs once stdin is closed, offering its
+/// lines the same way; `$FAKE_FZF_WAIT` holds the choice until then.
+const FAKE_FZF: &str = r#"#!/usr/bin/env bash
+query= filter= select_1= print_query= ansi= reload= wait=$FAKE_FZF_WAIT
+while [ $# -gt 0 ]; do
+    case "$1" in
+        --query) query=$2; shift ;;
+        --filter) filter=1; query=$2; shift ;;
+        --select-1) select_1=1 ;;
+        --print-query) print_query=1 ;;
+        --ansi) ansi=1 ;;
+        --bind=load:*reload-sync\(*) reload=${1#*reload-sync(}; reload=${reload%)} ;;
+    esac
+    shift
+done
+if [ -n "$print_query" ]; then
+    printf '%s\n' "$FAKE_FZF_QUERY"
+    if [ -n "$FAKE_FZF_KEY" ]; then
+        printf '%s\n' "$FAKE_FZF_KEY"
+        exit 0
+    fi
+    printf '\n'
+fi
+if [ -n "$filter$select_1" ]; then
+    matches=$(tee -a "$FAKE_FZF_LOG" | grep -iF -- "$query")
+    [ -z "$matches" ] && exit 1
+    if [ -n "$filter" ] || [ "$(printf '%s\n' "$matches" | wc -l)" -eq 1 ]; then
+        printf '%s\n' "$matches"
+        exit 0
+    fi
+    printf '%s\n' "$matches" | grep -qxF -- "$FAKE_FZF_SELECT" || exit 130
+    printf '%s\n' "$FAKE_FZF_SELECT"
+    exit 0
+fi
+[ -z "$FAKE_FZF_SELECT" ] && exit 130
+offer() {
+    while IFS= read -r -t 10 line || [ -n "$line" ]; do
+        entry=$line
+        if [ -n "$ansi" ]; then
+            entry=${line%$'\t'*}
+            printf '%s\n' "${line##*$'\t'}" | sed 's/\x1b\[[0-9;]*m//g' >> "$FAKE_FZF_COLUMN_LOG"
+        fi
+        printf '%s\n' "$entry" >> "$FAKE_FZF_LOG"
+        if [ -z "$wait" ] && [ "$entry" = "$FAKE_FZF_SELECT" ]; then
+            printf '%s\n' "$line"
+            exit 0
+        fi
+        line=
+    done
+}
+offer
+if [ -n "$reload" ]; then
+    wait=
+    offer < <(sh -c "$reload")
+fi
+exit 1
+"#;
+
+/// The `-L` socket name every test's server runs on.
+pub const SOCKET: &str = "leap-test";
+
+pub fn tmux_available() -> bool {
+    let found = |program: &str| {
+        Command::new(program)
+            .arg("-V")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok()
+    };
+    found("tmux") && Command::new("script").arg("--version").output().is_ok()
+}
+
+/// Skips the current test when tmux or `script` isn't installed.
+#[macro_export]
+macro_rules! require_tmux {
+    () => {
+        if !common::tmux_available() {
+            eprintln!("skipping: tmux or script is not installed");
+            return;
+        }
+    };
+}
+
+pub struct Env {
+    root: TempDir,
+    clients: Vec<Child>,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        let root = tempfile::tempdir().unwrap();
+        for dir in ["home", "tmux", "bin"] {
+            fs::create_dir(root.path().join(dir)).unwrap();
+        }
+        let fzf = root.path().join("bin/fzf");
+        fs::write(&fzf, FAKE_FZF).unwrap();
+        fs::set_permissions(&fzf, fs::Permissions::from_mode(0o755)).unwrap();
+        Self {
+            root,
+            clients: Vec::new(),
+        }
+    }
+
+    pub fn home(&self) -> PathBuf {
+        self.root.path().join("home")
+    }
+
+    /// Creates a directory under `HOME` and returns its absolute path.
+    pub fn mkdir(&self, relative: &str) -> String {
+        let dir = self.home().join(relative);
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_str().unwrap().to_string()
+    }
+
+    pub fn write_projects(&self, contents: &str) {
+        fs::write(self.home().join(".projects"), contents).unwrap();
+    }
+
+    pub fn projects(&self) -> String {
+        fs::read_to_string(self.home().join(".projects")).unwrap_or_default()
+    }
+
+    pub fn cache(&self) -> String {
+        fs::read_to_string(self.home().join(".projects_cache")).unwrap_or_default()
+    }
+
+    /// Runs git in `dir` with a committer identity set, asserting that it
+    /// succeeds.
+    pub fn git(&self, dir: impl AsRef<Path>, args: &[&str]) {
+        let mut command = Command::new("git");
+        self.apply(&mut command);
+        let output = command
+            .args(["-c", "user.name=t", "-c", "user.email=t@t", "-C"])
+            .arg(dir.as_ref())
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+    }
+
+    /// Makes `git status` take `seconds` longer, leaving every other git
+    /// command to the real git.
+    pub fn slow_git_status(&self, seconds: u32) {
+        let git = self.root.path().join("bin/git");
+        fs::write(
+            &git,
+            format!(
+                "#!/usr/bin/env bash\n\
+                 for arg in \"$@\"; do [ \"$arg\" = status ] && sleep {seconds}; done\n\
+                 PATH=${{PATH#*:}} exec git \"$@\"\n"
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&git, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    /// Every line the fake finder was offered, in order.
+    pub fn finder_log(&self) -> Vec<String> {
+        self.read_log("fzf.log")
+    }
+
+    /// The git status columns the picker showed, in the order of
+    /// [`Self::finder_log`].
+    pub fn finder_columns(&self) -> Vec<String> {
+        self.read_log("fzf-columns.log")
+    }
+
+    fn read_log(&self, name: &str) -> Vec<String> {
+        fs::read_to_string(self.root.path().join(name))
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn socket_dir(&self) -> PathBuf {
+        self.root.path().join("tmux")
+    }
+
+    fn socket_path(&self, name: &str) -> PathBuf {
+        let uid = String::from_utf8(Command::new("id").arg("-u").output().unwrap().stdout)
+            .unwrap()
+            .trim()
+            .to_string();
+        self.socket_dir().join(format!("tmux-{uid}/{name}"))
+    }
+
+    fn apply(&self, command: &mut Command) {
+        let path = std::env::var("PATH").unwrap_or_default();
+        command
+            .env("HOME", self.home())
+            .env("TMUX_TMPDIR", self.socket_dir())
+            .env(
+                "PATH",
+                format!("{}:{path}", self.root.path().join("bin").display()),
+            )
+            .env("FAKE_FZF_LOG", self.root.path().join("fzf.log"))
+            .env(
+                "FAKE_FZF_COLUMN_LOG",
+                self.root.path().join("fzf-columns.log"),
+            )
+            .env_remove("TMUX")
+            .env_remove("TMUX_PANE")
+            .env_remove("TMUX_LEAP_SOCKET_PATH")
+            .env_remove("TMUX_LEAP_CONFIG")
+            .env_remove("TMUX_LEAP_STATE")
+            .env_remove("TMUX_LEAP_PROFILE")
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_STATE_HOME")
+            .env_remove("FAKE_FZF_SELECT")
+            .env_remove("FAKE_FZF_QUERY")
+            .env_remove("FAKE_FZF_KEY")
+            .env_remove("FAKE_FZF_WAIT")
+            .env_remove("TMUX_LEAP_NEW_ROOT")
+            .env_remove("TMUX_LEAP_GIT_INIT")
+            .env_remove("TMUX_LEAP_CLONE_ROOT")
+            .env_remove("TMUX_LEAP_CONTROL_MODE")
+            .env_remove("EDITOR")
+            .current_dir(self.home());
+    }
+
+    /// Runs tmux against this environment's private server.
+    pub fn tmux(&self, args: &[&str]) -> Output {
+        self.tmux_on(SOCKET, args)
+    }
+
+    /// Runs tmux against another server in this environment.
+    pub fn tmux_on(&self, socket: &str, args: &[&str]) -> Output {
+        let mut command = Command::new("tmux");
+        self.apply(&mut command);
+        command.arg("-L").arg(socket).args(args).output().unwrap()
+    }
+
+    pub fn sessions(&self) -> Vec<String> {
+        self.sessions_on(SOCKET)
+    }
+
+    pub fn sessions_on(&self, socket: &str) -> Vec<String> {
+        let output = self.tmux_on(socket, &["list-sessions", "-F", "#{session_name}"]);
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Starts a detached session and attaches a client to it through a
+    /// pseudo-terminal, so `switch-client` has something to switch.
+    pub fn start_client(&mut self, session: &str) {
+        assert!(self
+            .tmux(&["new-session", "-d", "-s", session])
+            .status
+            .success());
+        let mut command = Command::new("script");
+        self.apply(&mut command);
+        let client = command
+            .args([
+                "-qfc",
+                &format!("tmux -L {SOCKET} attach -t {session}"),
+                "/dev/null",
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        self.clients.push(client);
+        self.wait_for(|env| !env.client_sessions().is_empty());
+    }
+
+    /// Attaches a control-mode client to `session`, like iTerm2's
+    /// `tmux -CC` integration.
+    pub fn start_control_client(&mut self, session: &str) {
+        let mut command = Command::new("tmux");
+        self.apply(&mut command);
+        let client = command
+            .args(["-L", SOCKET, "-C", "attach", "-t", session])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        self.clients.push(client);
+        self.wait_for(|env| !env.client_sessions().is_empty());
+    }
+
+    /// The session every attached client is currently showing.
+    pub fn client_sessions(&self) -> Vec<String> {
+        let output = self.tmux(&["list-clients", "-F", "#{client_session}"]);
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub fn wait_for(&self, condition: impl Fn(&Self) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition(self) {
+            assert!(Instant::now() < deadline, "timed out waiting for tmux");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Builds a tmux-leap invocation from outside tmux, selecting the test
+    /// server through `$TMUX_LEAP_SOCKET_NAME`.
+    pub fn leap(&self, args: &[&str]) -> Leap {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_tmux-leap"));
+        self.apply(&mut command);
+        command.env("TMUX_LEAP_SOCKET_NAME", SOCKET).args(args);
+        Leap {
+            command,
+            stdin: String::new(),
+        }
+    }
+
+    /// Builds a tmux-leap invocation as if run from a pane of the client.
+    pub fn leap_inside(&self, args: &[&str]) -> Leap {
+        let pid = String::from_utf8(self.tmux(&["display-message", "-p", "#{pid}"]).stdout)
+            .unwrap()
+            .trim()
+            .to_string();
+        let mut leap = self.leap(args);
+        leap.command.env(
+            "TMUX",
+            format!("{},{pid},0", self.socket_path(SOCKET).display()),
+        );
+        leap
+    }
+}
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        // Tests may start servers on other sockets too, so stop them all
+        let sockets = self.socket_path(SOCKET).parent().unwrap().to_path_buf();
+        for socket in fs::read_dir(sockets).into_iter().flatten().flatten() {
+            let _ = self.tmux_on(&socket.file_name().to_string_lossy(), &["kill-server"]);
+        }
+        for client in &mut self.clients {
+            let _ = client.kill();
+            let _ = client.wait();
+        }
+    }
+}
+
+pub struct Leap {
+    command: Command,
+    stdin: String,
+}
+
+impl Leap {
+    /// Makes the fake finder choose `line`.
+    pub fn select(mut self, line: &str) -> Self {
+        self.command.env("FAKE_FZF_SELECT", line);
+        self
+    }
+
+    /// Makes the fake finder type `query` and press `key` on it.
+    pub fn press(mut self, query: &str, key: &str) -> Self {
+        self.command
+            .env("FAKE_FZF_QUERY", query)
+            .env("FAKE_FZF_KEY", key);
+        self
+    }
+
+    pub fn env(mut self, key: &str, value: impl AsRef<std::ffi::OsStr>) -> Self {
+        self.command.env(key, value);
+        self
+    }
+
+    /// Runs tmux-leap from `dir` instead of `HOME`.
+    pub fn current_dir(mut self, dir: &Path) -> Self {
+        self.command.current_dir(dir);
+        self
+    }
+
+    pub fn stdin(mut self, input: &str) -> Self {
+        self.stdin = input.to_string();
+        self
+    }
+
+    pub fn run(mut self) -> Output {
+        let mut child = self
+            .command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(self.stdin.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Runs tmux-leap and stops reading its output after `bytes`, as
+    /// `| head -c` does.
+    pub fn run_until(mut self, bytes: usize) -> Output {
+        let mut child = self
+            .command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = vec![0; bytes];
+        child
+            .stdout
+            .take()
+            .unwrap()
+            .read_exact(&mut stdout)
+            .unwrap();
+        let mut output = child.wait_with_output().unwrap();
+        output.stdout = stdout;
+        output
+    }
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+/// Shortens an absolute path under `home` the way tmux-leap displays it.
+pub fn display(home: &Path, path: &str) -> String {
+    format!(
+        "~/{}",
+        Path::new(path).strip_prefix(home).unwrap().display()
+    )
+}